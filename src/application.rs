@@ -0,0 +1,140 @@
+use crate::bind;
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+
+use crate::variant::{hash_table_to_map, Variant};
+use crate::{Error, Result};
+
+/// An application installed on a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Application {
+    /// Application identifier, e.g. the Android package name.
+    pub identifier: String,
+    /// Display name.
+    pub name: String,
+    /// Process ID of the main process, `None` when the application is not running.
+    pub pid: Option<u32>,
+    /// Extra details, filled in according to the [`Scope`] of the query.
+    pub parameters: HashMap<String, Variant>,
+}
+
+impl Application {
+    pub(crate) fn from_raw(application_ptr: *mut bind::FridaApplication) -> Self {
+        let identifier =
+            unsafe { CStr::from_ptr(bind::frida_application_get_identifier(application_ptr)) };
+        let name = unsafe { CStr::from_ptr(bind::frida_application_get_name(application_ptr)) };
+        let pid = unsafe { bind::frida_application_get_pid(application_ptr) };
+        let parameters = unsafe { bind::frida_application_get_parameters(application_ptr) };
+        Application {
+            identifier: identifier.to_string_lossy().into_owned(),
+            name: name.to_string_lossy().into_owned(),
+            pid: (pid != 0).then_some(pid),
+            parameters: unsafe { hash_table_to_map(parameters) },
+        }
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+/// How much detail a query fills into `parameters`
+pub enum Scope {
+    /// Identifier, name and pid only
+    #[default]
+    Minimal = bind::FridaScope_FRIDA_SCOPE_MINIMAL as _,
+
+    /// Also version, build, sources and the like
+    Metadata = bind::FridaScope_FRIDA_SCOPE_METADATA as _,
+
+    /// Also icons
+    Full = bind::FridaScope_FRIDA_SCOPE_FULL as _,
+}
+
+/// Application query options
+pub struct ApplicationQueryOptions<'a> {
+    pub(crate) options_ptr: *mut bind::FridaApplicationQueryOptions,
+    phantom: PhantomData<&'a bind::FridaApplicationQueryOptions>,
+}
+
+impl<'a> ApplicationQueryOptions<'a> {
+    /// Create options that match every application
+    pub fn new() -> Self {
+        Self {
+            options_ptr: unsafe { bind::frida_application_query_options_new() },
+            phantom: PhantomData,
+        }
+    }
+
+    /// Only return the applications with these identifiers
+    ///
+    /// Fails with [`Error::CStringFailed`] if an identifier contains a NUL byte.
+    pub fn select_identifiers<S, L>(self, identifiers: L) -> Result<Self>
+    where
+        S: AsRef<str>,
+        L: IntoIterator<Item = S>,
+    {
+        for identifier in identifiers {
+            let identifier = CString::new(identifier.as_ref()).map_err(|_| Error::CStringFailed)?;
+            unsafe {
+                bind::frida_application_query_options_select_identifier(
+                    self.options_ptr,
+                    identifier.as_ptr(),
+                )
+            };
+        }
+        Ok(self)
+    }
+
+    /// Set how much detail is returned
+    pub fn scope(self, scope: Scope) -> Self {
+        unsafe { bind::frida_application_query_options_set_scope(self.options_ptr, scope as _) }
+        self
+    }
+}
+
+impl<'a> Default for ApplicationQueryOptions<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Drop for ApplicationQueryOptions<'a> {
+    fn drop(&mut self) {
+        unsafe { bind::frida_unref(self.options_ptr as _) }
+    }
+}
+
+/// Frontmost application query options
+pub struct FrontmostQueryOptions<'a> {
+    pub(crate) options_ptr: *mut bind::FridaFrontmostQueryOptions,
+    phantom: PhantomData<&'a bind::FridaFrontmostQueryOptions>,
+}
+
+impl<'a> FrontmostQueryOptions<'a> {
+    /// Create options with minimal detail
+    pub fn new() -> Self {
+        Self {
+            options_ptr: unsafe { bind::frida_frontmost_query_options_new() },
+            phantom: PhantomData,
+        }
+    }
+
+    /// Set how much detail is returned
+    pub fn scope(self, scope: Scope) -> Self {
+        unsafe { bind::frida_frontmost_query_options_set_scope(self.options_ptr, scope as _) }
+        self
+    }
+}
+
+impl<'a> Default for FrontmostQueryOptions<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Drop for FrontmostQueryOptions<'a> {
+    fn drop(&mut self) {
+        unsafe { bind::frida_unref(self.options_ptr as _) }
+    }
+}
@@ -5,10 +5,69 @@ use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::{env, result};
 
+/// Default limit for script uploads, large enough for bundled Frida agents.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// Default number of seconds scripts survive a dropped connection to frida-server.
+pub const DEFAULT_PERSIST_TIMEOUT: u32 = 30;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub gg_package: String,
     pub path: String,
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    /// Scripts injected into gated spawns. Spawn gating is only enabled when this is not empty.
+    #[serde(default)]
+    pub auto_inject: Vec<AutoInject>,
+    /// Hold children of instrumented processes and load the parent's scripts into them.
+    #[serde(default)]
+    pub follow_children: bool,
+    /// Inject into the frontmost application when a request has no `pid`.
+    #[serde(default)]
+    pub frontmost_by_default: bool,
+    /// Seconds sessions keep their scripts after the connection to frida-server drops, 0 to
+    /// detach right away.
+    #[serde(default = "default_persist_timeout")]
+    pub persist_timeout: u32,
+}
+
+/// Loads `script` into every spawned process whose identifier matches `pattern`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AutoInject {
+    /// Identifier to match, `*` matches any run of characters.
+    pub pattern: String,
+    /// Path of the script source to load.
+    pub script: String,
+}
+
+impl AutoInject {
+    pub fn matches(&self, identifier: &str) -> bool {
+        let mut parts = self.pattern.split('*');
+        let first = parts.next().unwrap_or_default();
+        let Some(mut rest) = identifier.strip_prefix(first) else {
+            return false;
+        };
+        let mut parts: Vec<&str> = parts.collect();
+        let Some(last) = parts.pop() else {
+            return rest.is_empty();
+        };
+        for part in parts {
+            match rest.find(part) {
+                Some(index) => rest = &rest[index + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+}
+
+fn default_max_body_size() -> usize {
+    DEFAULT_MAX_BODY_SIZE
+}
+
+fn default_persist_timeout() -> u32 {
+    DEFAULT_PERSIST_TIMEOUT
 }
 
 pub fn configure() -> Result<Config> {
@@ -39,6 +98,11 @@ fn reconf(json_path: &PathBuf) -> Result<Config> {
             let config = Config {
                 gg_package: pkg,
                 path,
+                max_body_size: DEFAULT_MAX_BODY_SIZE,
+                auto_inject: Vec::new(),
+                follow_children: false,
+                frontmost_by_default: false,
+                persist_timeout: DEFAULT_PERSIST_TIMEOUT,
             };
             let json_content =
                 serde_json::to_string_pretty(&config).expect("Failed to serialize Config.json.");
@@ -55,7 +119,7 @@ fn reconf(json_path: &PathBuf) -> Result<Config> {
     }
 }
 fn validate_config(config: &Config) -> bool {
-    !config.gg_package.is_empty() && !config.path.is_empty()
+    !config.gg_package.is_empty() && !config.path.is_empty() && config.max_body_size > 0
 }
 fn traverse_files(dir: &Path, app_package: &str) -> Option<String> {
     let mut result: Option<String> = None;
@@ -126,3 +190,47 @@ fn find_pkg() -> Option<(String, String)> {
         Err(_) => return None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str) -> AutoInject {
+        AutoInject {
+            pattern: pattern.to_string(),
+            script: "hook.js".to_string(),
+        }
+    }
+
+    #[test]
+    fn matches_literal_patterns_exactly() {
+        assert!(rule("com.example.game").matches("com.example.game"));
+        assert!(!rule("com.example.game").matches("com.example.game:remote"));
+        assert!(!rule("com.example.game").matches("com.example"));
+        assert!(rule("").matches(""));
+        assert!(!rule("").matches("com.example.game"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(rule("*").matches(""));
+        assert!(rule("*").matches("com.example.game"));
+        assert!(rule("com.example.*").matches("com.example.game"));
+        assert!(rule("com.example.*").matches("com.example."));
+        assert!(!rule("com.example.*").matches("com.other.game"));
+        assert!(rule("*.game").matches("com.example.game"));
+        assert!(!rule("*.game").matches("com.example.game2"));
+        assert!(rule("com.*.game").matches("com.example.game"));
+        assert!(rule("com.*.*.game").matches("com.a.b.game"));
+        assert!(!rule("com.*.*.game").matches("com.a.game"));
+    }
+
+    #[test]
+    fn does_not_reuse_characters_between_parts() {
+        assert!(!rule("a*a").matches("a"));
+        assert!(rule("a*a").matches("aa"));
+        assert!(!rule("ab*bc").matches("abc"));
+        assert!(rule("a*b*b").matches("abb"));
+        assert!(!rule("a*bc*c").matches("abc"));
+    }
+}
@@ -7,17 +7,27 @@
 use crate::bind;
 
 use std::collections::HashMap;
-use std::ffi::{CStr, CString};
+use std::ffi::{c_void, CStr, CString};
+use std::future::Future;
 use std::marker::PhantomData;
+use std::sync::Arc;
+use tokio::sync::Notify;
 
-use crate::process::Process;
-use crate::session::Session;
-use crate::variant::Variant;
-use crate::{Error, Result, SpawnOptions};
+use crate::application::{Application, ApplicationQueryOptions, FrontmostQueryOptions};
+use crate::error::take_error;
+use crate::process::{Child, Process, ProcessMatchOptions, ProcessQueryOptions, Spawn};
+use crate::session::{Session, SessionOptions};
+use crate::variant::{hash_table_to_map, Variant};
+use crate::{
+    connect_signal, disconnect_signals, Error, ObjectRef, Result, SendPtr, SpawnOptions, FRIDA,
+};
 
 /// Access to a Frida device.
 pub struct Device<'a> {
     pub(crate) device_ptr: *mut bind::_FridaDevice,
+    /// Waker registered through [`notify_gated`](Device::notify_gated) and the ids of the
+    /// signal handlers that wake it.
+    gated: Option<(Arc<Notify>, Vec<bind::gulong>)>,
     phantom: PhantomData<&'a bind::_FridaDevice>,
 }
 
@@ -25,6 +35,7 @@ impl<'a> Device<'a> {
     pub(crate) fn from_raw(device_ptr: *mut bind::_FridaDevice) -> Device<'a> {
         Device {
             device_ptr,
+            gated: None,
             phantom: PhantomData,
         }
     }
@@ -93,31 +104,44 @@ impl<'a> Device<'a> {
         };
 
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::DeviceQuerySystemParametersFailed { code, message });
         }
 
-        let mut iter: bind::GHashTableIter =
-            unsafe { std::mem::MaybeUninit::zeroed().assume_init() };
-        unsafe { bind::_frida_g_hash_table_iter_init(&mut iter, ht) };
-        let size = unsafe { bind::_frida_g_hash_table_size(ht) };
-        let mut map = HashMap::with_capacity(size as usize);
-
-        let mut key = std::ptr::null_mut();
-        let mut val = std::ptr::null_mut();
-        while (unsafe { bind::_frida_g_hash_table_iter_next(&mut iter, &mut key, &mut val) }
-            != bind::FALSE as i32)
-        {
-            let key = unsafe { CStr::from_ptr(key as _) };
-            let val = unsafe { Variant::from_ptr(val as _) };
-            map.insert(key.to_string_lossy().to_string(), val);
-        }
+        Ok(unsafe { take_hash_table(ht) })
+    }
 
-        Ok(map)
+    /// Returns the device's system parameters without blocking the calling thread.
+    pub fn query_system_parameters_async(
+        &self,
+    ) -> impl Future<Output = Result<HashMap<String, Variant>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_query_system_parameters(
+                    device.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let ht = unsafe {
+                    bind::frida_device_query_system_parameters_finish(
+                        device.get(),
+                        result,
+                        &mut error,
+                    )
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::DeviceQuerySystemParametersFailed { code, message });
+                }
+                Ok(unsafe { take_hash_table(ht) })
+            },
+        )
     }
 
     /// Returns if the device is lost or not.
@@ -125,6 +149,38 @@ impl<'a> Device<'a> {
         unsafe { bind::frida_device_is_lost(self.device_ptr) == 1 }
     }
 
+    /// Wakes `notify` whenever spawn or child gating holds a new process, so callers can wait
+    /// for [`enumerate_pending_spawn`](Device::enumerate_pending_spawn) or
+    /// [`enumerate_pending_children`](Device::enumerate_pending_children) to have something to
+    /// return instead of polling them. Replaces the previous waker, if any.
+    pub fn notify_gated(&mut self, notify: Arc<Notify>) {
+        self.disconnect_gated();
+        let user_data = Arc::as_ptr(&notify) as *mut c_void;
+        let signal_handler_ids = unsafe {
+            vec![
+                connect_signal(
+                    self.device_ptr,
+                    c"spawn-added",
+                    call_on_gated as *mut c_void,
+                    user_data,
+                ),
+                connect_signal(
+                    self.device_ptr,
+                    c"child-added",
+                    call_on_gated as *mut c_void,
+                    user_data,
+                ),
+            ]
+        };
+        self.gated = Some((notify, signal_handler_ids));
+    }
+
+    fn disconnect_gated(&mut self) {
+        if let Some((notify, ids)) = self.gated.take() {
+            disconnect_signals(self.device_ptr, ids, notify);
+        }
+    }
+
     /// Returns all processes.
     pub fn enumerate_processes<'b>(&'a self) -> Result<Vec<Process<'b>>>
     where
@@ -143,7 +199,8 @@ impl<'a> Device<'a> {
         };
 
         if !error.is_null() {
-            return Err(Error::ProcessesEnumerationFailed);
+            let (code, message) = take_error(error);
+            return Err(Error::ProcessesEnumerationFailed { code, message });
         } else {
             let num_processes = unsafe { bind::frida_process_list_size(processes_ptr) };
             processes.reserve(num_processes as usize);
@@ -159,11 +216,409 @@ impl<'a> Device<'a> {
         Ok(processes)
     }
 
+    /// Returns all processes without blocking the calling thread.
+    pub fn enumerate_processes_async(
+        &self,
+    ) -> impl Future<Output = Result<Vec<Process<'static>>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_enumerate_processes(
+                    device.get(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let processes_ptr = unsafe {
+                    bind::frida_device_enumerate_processes_finish(device.get(), result, &mut error)
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::ProcessesEnumerationFailed { code, message });
+                }
+
+                let num_processes = unsafe { bind::frida_process_list_size(processes_ptr) };
+                let processes = (0..num_processes)
+                    .map(|i| {
+                        Process::from_raw(unsafe { bind::frida_process_list_get(processes_ptr, i) })
+                    })
+                    .collect();
+                unsafe { bind::frida_unref(processes_ptr as _) };
+                Ok(processes)
+            },
+        )
+    }
+
+    /// Returns the processes matching `options`.
+    pub fn enumerate_processes_with(
+        &self,
+        options: &ProcessQueryOptions,
+    ) -> Result<Vec<Process<'a>>> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let processes_ptr = unsafe {
+            bind::frida_device_enumerate_processes_sync(
+                self.device_ptr,
+                options.options_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::ProcessesEnumerationFailed { code, message });
+        }
+
+        Ok(processes_from_list(processes_ptr))
+    }
+
+    /// Returns the processes matching `options` without blocking the calling thread.
+    pub fn enumerate_processes_with_async(
+        &self,
+        options: &ProcessQueryOptions,
+    ) -> impl Future<Output = Result<Vec<Process<'static>>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        let options = ObjectRef::new(options.options_ptr);
+
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_enumerate_processes(
+                    device.get(),
+                    options.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                );
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let processes_ptr = unsafe {
+                    bind::frida_device_enumerate_processes_finish(device.get(), result, &mut error)
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::ProcessesEnumerationFailed { code, message });
+                }
+                Ok(processes_from_list(processes_ptr))
+            },
+        )
+    }
+
+    /// Returns the process named exactly `name`, or `None` if there is none.
+    ///
+    /// With a [timeout](ProcessMatchOptions::timeout), waits for such a process to appear.
+    pub fn find_process_by_name(
+        &self,
+        name: &str,
+        options: &ProcessMatchOptions,
+    ) -> Result<Option<Process<'a>>> {
+        let name = CString::new(name).map_err(|_| Error::CStringFailed)?;
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let process_ptr = unsafe {
+            bind::frida_device_find_process_by_name_sync(
+                self.device_ptr,
+                name.as_ptr(),
+                options.options_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::ProcessLookupFailed { code, message });
+        }
+
+        Ok((!process_ptr.is_null()).then(|| Process::from_raw(process_ptr)))
+    }
+
+    /// Returns the process named exactly `name` without blocking the calling thread.
+    pub fn find_process_by_name_async(
+        &self,
+        name: &str,
+        options: &ProcessMatchOptions,
+    ) -> impl Future<Output = Result<Option<Process<'static>>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        let lookup = CString::new(name).map(|name| {
+            let options = ObjectRef::new(options.options_ptr);
+
+            FRIDA.run_async(
+                self.device_ptr as _,
+                move |callback, user_data| unsafe {
+                    bind::frida_device_find_process_by_name(
+                        device.get(),
+                        name.as_ptr(),
+                        options.get(),
+                        std::ptr::null_mut(),
+                        callback,
+                        user_data,
+                    );
+                },
+                move |result| {
+                    let mut error: *mut bind::GError = std::ptr::null_mut();
+                    let process_ptr = unsafe {
+                        bind::frida_device_find_process_by_name_finish(
+                            device.get(),
+                            result,
+                            &mut error,
+                        )
+                    };
+                    if !error.is_null() {
+                        let (code, message) = take_error(error);
+                        return Err(Error::ProcessLookupFailed { code, message });
+                    }
+                    Ok((!process_ptr.is_null()).then(|| Process::from_raw(process_ptr)))
+                },
+            )
+        });
+
+        async move { lookup.map_err(|_| Error::CStringFailed)?.await }
+    }
+
+    /// Returns the process with the given pid.
+    pub fn get_process_by_pid(
+        &self,
+        pid: u32,
+        options: &ProcessMatchOptions,
+    ) -> Result<Process<'a>> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let process_ptr = unsafe {
+            bind::frida_device_get_process_by_pid_sync(
+                self.device_ptr,
+                pid,
+                options.options_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            return Err(lookup_error(error, pid));
+        }
+
+        Ok(Process::from_raw(process_ptr))
+    }
+
+    /// Returns the process with the given pid without blocking the calling thread.
+    pub fn get_process_by_pid_async(
+        &self,
+        pid: u32,
+        options: &ProcessMatchOptions,
+    ) -> impl Future<Output = Result<Process<'static>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        let options = ObjectRef::new(options.options_ptr);
+
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_get_process_by_pid(
+                    device.get(),
+                    pid,
+                    options.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                );
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let process_ptr = unsafe {
+                    bind::frida_device_get_process_by_pid_finish(device.get(), result, &mut error)
+                };
+                if !error.is_null() {
+                    return Err(lookup_error(error, pid));
+                }
+                Ok(Process::from_raw(process_ptr))
+            },
+        )
+    }
+
+    /// Returns the application in the foreground, or `None` if there is none.
+    pub fn get_frontmost_application(
+        &self,
+        options: &FrontmostQueryOptions,
+    ) -> Result<Option<Application>> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let application_ptr = unsafe {
+            bind::frida_device_get_frontmost_application_sync(
+                self.device_ptr,
+                options.options_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::FrontmostApplicationFailed { code, message });
+        }
+
+        Ok(take_application(application_ptr))
+    }
+
+    /// Returns the application in the foreground without blocking the calling thread.
+    pub fn get_frontmost_application_async(
+        &self,
+        options: &FrontmostQueryOptions,
+    ) -> impl Future<Output = Result<Option<Application>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        let options = ObjectRef::new(options.options_ptr);
+
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_get_frontmost_application(
+                    device.get(),
+                    options.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                );
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let application_ptr = unsafe {
+                    bind::frida_device_get_frontmost_application_finish(
+                        device.get(),
+                        result,
+                        &mut error,
+                    )
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::FrontmostApplicationFailed { code, message });
+                }
+                Ok(take_application(application_ptr))
+            },
+        )
+    }
+
+    /// Returns the applications matching `options`.
+    pub fn enumerate_applications(
+        &self,
+        options: &ApplicationQueryOptions,
+    ) -> Result<Vec<Application>> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let applications_ptr = unsafe {
+            bind::frida_device_enumerate_applications_sync(
+                self.device_ptr,
+                options.options_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::ApplicationsEnumerationFailed { code, message });
+        }
+
+        Ok(applications_from_list(applications_ptr))
+    }
+
+    /// Returns the applications matching `options` without blocking the calling thread.
+    pub fn enumerate_applications_async(
+        &self,
+        options: &ApplicationQueryOptions,
+    ) -> impl Future<Output = Result<Vec<Application>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        let options = ObjectRef::new(options.options_ptr);
+
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_enumerate_applications(
+                    device.get(),
+                    options.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                );
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let applications_ptr = unsafe {
+                    bind::frida_device_enumerate_applications_finish(
+                        device.get(),
+                        result,
+                        &mut error,
+                    )
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::ApplicationsEnumerationFailed { code, message });
+                }
+                Ok(applications_from_list(applications_ptr))
+            },
+        )
+    }
+
+    /// Attaches to `pid` with `options`, e.g. to make the session persistent.
+    pub fn attach_with(&self, pid: u32, options: &SessionOptions) -> Result<Session<'a>> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let session = unsafe {
+            bind::frida_device_attach_sync(
+                self.device_ptr,
+                pid,
+                options.options_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if error.is_null() {
+            Ok(Session::from_raw(session))
+        } else {
+            let (code, message) = take_error(error);
+            Err(Error::DeviceAttachError { code, message })
+        }
+    }
+
+    /// Attaches to `pid` with `options` without blocking the calling thread.
+    pub fn attach_with_async(
+        &self,
+        pid: u32,
+        options: &SessionOptions,
+    ) -> impl Future<Output = Result<Session<'static>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        let options = ObjectRef::new(options.options_ptr);
+
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_attach(
+                    device.get(),
+                    pid,
+                    options.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                );
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let session =
+                    unsafe { bind::frida_device_attach_finish(device.get(), result, &mut error) };
+                if error.is_null() {
+                    Ok(Session::from_raw(session))
+                } else {
+                    let (code, message) = take_error(error);
+                    Err(Error::DeviceAttachError { code, message })
+                }
+            },
+        )
+    }
+
     /// Creates [`Session`] and attaches the device to the current PID.
-    pub fn attach<'b>(&'a self, pid: u32) -> Result<Session<'b>>
-    where
-        'a: 'b,
-    {
+    ///
+    /// The session holds its own reference and may outlive this handle.
+    pub fn attach(&self, pid: u32) -> Result<Session<'a>> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
         let session = unsafe {
             bind::frida_device_attach_sync(
@@ -178,10 +633,45 @@ impl<'a> Device<'a> {
         if error.is_null() {
             Ok(Session::from_raw(session))
         } else {
-            Err(Error::DeviceAttachError)
+            let (code, message) = take_error(error);
+            Err(Error::DeviceAttachError { code, message })
         }
     }
 
+    /// Attaches to `pid` without blocking the calling thread.
+    ///
+    /// The returned future does not borrow the device.
+    pub fn attach_async(
+        &self,
+        pid: u32,
+    ) -> impl Future<Output = Result<Session<'static>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_attach(
+                    device.get(),
+                    pid,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let session =
+                    unsafe { bind::frida_device_attach_finish(device.get(), result, &mut error) };
+                if error.is_null() {
+                    Ok(Session::from_raw(session))
+                } else {
+                    let (code, message) = take_error(error);
+                    Err(Error::DeviceAttachError { code, message })
+                }
+            },
+        )
+    }
+
     /// Spawn a process on the device
     ///
     /// Returns the PID of the newly spawned process.
@@ -202,11 +692,7 @@ impl<'a> Device<'a> {
         };
 
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::SpawnFailed { code, message });
         }
 
@@ -221,17 +707,277 @@ impl<'a> Device<'a> {
         };
 
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::ResumeFailed { code, message });
         }
 
         Ok(())
     }
 
+    /// Spawns `program` without blocking the calling thread.
+    ///
+    /// Like [`spawn`](Device::spawn), the process is left suspended until it is resumed.
+    pub fn spawn_async(
+        &self,
+        program: &str,
+        options: &SpawnOptions,
+    ) -> impl Future<Output = Result<u32>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        let start = CString::new(program).map(|program| {
+            let options = ObjectRef::new(options.options_ptr);
+
+            FRIDA.run_async(
+                self.device_ptr as _,
+                move |callback, user_data| unsafe {
+                    bind::frida_device_spawn(
+                        device.get(),
+                        program.as_ptr(),
+                        options.get(),
+                        std::ptr::null_mut(),
+                        callback,
+                        user_data,
+                    );
+                },
+                move |result| {
+                    let mut error: *mut bind::GError = std::ptr::null_mut();
+                    let pid = unsafe {
+                        bind::frida_device_spawn_finish(device.get(), result, &mut error)
+                    };
+                    if !error.is_null() {
+                        let (code, message) = take_error(error);
+                        return Err(Error::SpawnFailed { code, message });
+                    }
+                    Ok(pid)
+                },
+            )
+        });
+
+        async move { start.map_err(|_| Error::CStringFailed)?.await }
+    }
+
+    /// Resumes the process with given pid without blocking the calling thread.
+    pub fn resume_async(&self, pid: u32) -> impl Future<Output = Result<()>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_resume(
+                    device.get(),
+                    pid,
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe { bind::frida_device_resume_finish(device.get(), result, &mut error) };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::ResumeFailed { code, message });
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Kills a process on the device without blocking the calling thread.
+    pub fn kill_async(&self, pid: u32) -> impl Future<Output = Result<()>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_kill(
+                    device.get(),
+                    pid,
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe { bind::frida_device_kill_finish(device.get(), result, &mut error) };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::KillFailed { code, message });
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Enables spawn gating: processes spawned from now on are held until resumed.
+    ///
+    /// Held processes are listed by [`enumerate_pending_spawn`](Device::enumerate_pending_spawn).
+    pub fn enable_spawn_gating(&self) -> Result<()> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        unsafe {
+            bind::frida_device_enable_spawn_gating_sync(
+                self.device_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::SpawnGatingFailed { code, message });
+        }
+
+        Ok(())
+    }
+
+    /// Enables spawn gating without blocking the calling thread.
+    pub fn enable_spawn_gating_async(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_enable_spawn_gating(
+                    device.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe {
+                    bind::frida_device_enable_spawn_gating_finish(device.get(), result, &mut error)
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::SpawnGatingFailed { code, message });
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Disables spawn gating. Processes that are already held stay suspended.
+    pub fn disable_spawn_gating(&self) -> Result<()> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        unsafe {
+            bind::frida_device_disable_spawn_gating_sync(
+                self.device_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::SpawnGatingFailed { code, message });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the processes held by spawn gating.
+    pub fn enumerate_pending_spawn(&self) -> Result<Vec<Spawn>> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let spawns_ptr = unsafe {
+            bind::frida_device_enumerate_pending_spawn_sync(
+                self.device_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::PendingSpawnEnumerationFailed { code, message });
+        }
+
+        Ok(spawns_from_list(spawns_ptr))
+    }
+
+    /// Returns the processes held by spawn gating without blocking the calling thread.
+    pub fn enumerate_pending_spawn_async(
+        &self,
+    ) -> impl Future<Output = Result<Vec<Spawn>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_enumerate_pending_spawn(
+                    device.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let spawns_ptr = unsafe {
+                    bind::frida_device_enumerate_pending_spawn_finish(
+                        device.get(),
+                        result,
+                        &mut error,
+                    )
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::PendingSpawnEnumerationFailed { code, message });
+                }
+                Ok(spawns_from_list(spawns_ptr))
+            },
+        )
+    }
+
+    /// Returns the children held by child gating on any session of this device.
+    pub fn enumerate_pending_children(&self) -> Result<Vec<Child>> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        let children_ptr = unsafe {
+            bind::frida_device_enumerate_pending_children_sync(
+                self.device_ptr,
+                std::ptr::null_mut(),
+                &mut error,
+            )
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::PendingChildrenEnumerationFailed { code, message });
+        }
+
+        Ok(children_from_list(children_ptr))
+    }
+
+    /// Returns the children held by child gating without blocking the calling thread.
+    pub fn enumerate_pending_children_async(
+        &self,
+    ) -> impl Future<Output = Result<Vec<Child>>> + Send + 'static {
+        let device = SendPtr::new(self.device_ptr);
+        FRIDA.run_async(
+            self.device_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_device_enumerate_pending_children(
+                    device.get(),
+                    std::ptr::null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                let children_ptr = unsafe {
+                    bind::frida_device_enumerate_pending_children_finish(
+                        device.get(),
+                        result,
+                        &mut error,
+                    )
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::PendingChildrenEnumerationFailed { code, message });
+                }
+                Ok(children_from_list(children_ptr))
+            },
+        )
+    }
+
     /// Kill a process on the device
     pub fn kill(&mut self, pid: u32) -> Result<()> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
@@ -240,11 +986,7 @@ impl<'a> Device<'a> {
         };
 
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::KillFailed { code, message });
         }
 
@@ -252,8 +994,109 @@ impl<'a> Device<'a> {
     }
 }
 
+/// Wraps the entries of a `FridaProcessList` and releases the list.
+fn processes_from_list<'a>(processes_ptr: *mut bind::FridaProcessList) -> Vec<Process<'a>> {
+    let num_processes = unsafe { bind::frida_process_list_size(processes_ptr) };
+    let processes = (0..num_processes)
+        .map(|i| Process::from_raw(unsafe { bind::frida_process_list_get(processes_ptr, i) }))
+        .collect();
+    unsafe { bind::frida_unref(processes_ptr as _) };
+    processes
+}
+
+/// Copies a nullable `FridaApplication` and releases it.
+fn take_application(application_ptr: *mut bind::FridaApplication) -> Option<Application> {
+    if application_ptr.is_null() {
+        return None;
+    }
+    let application = Application::from_raw(application_ptr);
+    unsafe { bind::frida_unref(application_ptr as _) };
+    Some(application)
+}
+
+/// Copies a system parameters table and releases it, it is owned by the caller.
+unsafe fn take_hash_table(ht: *mut bind::GHashTable) -> HashMap<String, Variant> {
+    let map = hash_table_to_map(ht);
+    bind::_frida_g_hash_table_unref(ht);
+    map
+}
+
+/// Copies a `FridaApplicationList` into owned [`Application`]s and releases the list.
+fn applications_from_list(applications_ptr: *mut bind::FridaApplicationList) -> Vec<Application> {
+    let num_applications = unsafe { bind::frida_application_list_size(applications_ptr) };
+    let applications = (0..num_applications)
+        .map(|i| {
+            let application_ptr = unsafe { bind::frida_application_list_get(applications_ptr, i) };
+            let application = Application::from_raw(application_ptr);
+            unsafe { bind::frida_unref(application_ptr as _) };
+            application
+        })
+        .collect();
+    unsafe { bind::frida_unref(applications_ptr as _) };
+    applications
+}
+
+/// Copies a `FridaSpawnList` into owned [`Spawn`]s and releases the list.
+fn spawns_from_list(spawns_ptr: *mut bind::FridaSpawnList) -> Vec<Spawn> {
+    let num_spawns = unsafe { bind::frida_spawn_list_size(spawns_ptr) };
+    let spawns = (0..num_spawns)
+        .map(|i| {
+            let spawn_ptr = unsafe { bind::frida_spawn_list_get(spawns_ptr, i) };
+            let spawn = Spawn::from_raw(spawn_ptr);
+            unsafe { bind::frida_unref(spawn_ptr as _) };
+            spawn
+        })
+        .collect();
+    unsafe { bind::frida_unref(spawns_ptr as _) };
+    spawns
+}
+
+/// Copies a `FridaChildList` into owned [`Child`]ren and releases the list.
+fn children_from_list(children_ptr: *mut bind::FridaChildList) -> Vec<Child> {
+    let num_children = unsafe { bind::frida_child_list_size(children_ptr) };
+    let children = (0..num_children)
+        .map(|i| {
+            let child_ptr = unsafe { bind::frida_child_list_get(children_ptr, i) };
+            let child = Child::from_raw(child_ptr);
+            unsafe { bind::frida_unref(child_ptr as _) };
+            child
+        })
+        .collect();
+    unsafe { bind::frida_unref(children_ptr as _) };
+    children
+}
+
+/// Turns a failed pid lookup into [`Error::ProcessNotFound`] when the process does not exist.
+fn lookup_error(error: *mut bind::GError, pid: u32) -> Error {
+    let (code, message) = take_error(error);
+    if code == bind::FridaError_FRIDA_ERROR_PROCESS_NOT_FOUND as i32 {
+        Error::ProcessNotFound { pid }
+    } else {
+        Error::ProcessLookupFailed { code, message }
+    }
+}
+
+unsafe extern "C" fn call_on_gated(
+    _device_ptr: *mut bind::_FridaDevice,
+    _item: *mut c_void,
+    user_data: *mut c_void,
+) {
+    // A permit is kept if nobody is waiting, so a process held between two waits is not missed.
+    (*(user_data as *const Notify)).notify_one();
+}
+
+/// Takes another reference to the device. The clone does not share the waker registered
+/// through [`notify_gated`](Device::notify_gated).
+impl<'a> Clone for Device<'a> {
+    fn clone(&self) -> Self {
+        unsafe { bind::_frida_g_object_ref(self.device_ptr as _) };
+        Device::from_raw(self.device_ptr)
+    }
+}
+
 impl<'a> Drop for Device<'a> {
     fn drop(&mut self) {
+        self.disconnect_gated();
         unsafe { bind::frida_unref(self.device_ptr as _) }
     }
 }
@@ -9,6 +9,7 @@ use std::marker::PhantomData;
 
 use crate::bind;
 use crate::device::{self, Device};
+use crate::error::take_error;
 use crate::DeviceType;
 use crate::Error;
 use crate::Frida;
@@ -58,6 +59,8 @@ impl<'a> DeviceManager<'a> {
                     Device::from_raw(unsafe { bind::frida_device_list_get(devices_ptr, i) });
                 devices.push(device);
             }
+        } else {
+            let _ = take_error(error);
         }
 
         unsafe { bind::frida_unref(devices_ptr as _) }
@@ -65,7 +68,7 @@ impl<'a> DeviceManager<'a> {
     }
 
     /// Returns the device of the specified type.
-    pub fn get_device_by_type(&'a self, r#type: DeviceType) -> Result<Device<'a>> {
+    pub fn get_device_by_type(&self, r#type: DeviceType) -> Result<Device<'a>> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
 
         let device_ptr = unsafe {
@@ -79,14 +82,15 @@ impl<'a> DeviceManager<'a> {
         };
 
         if !error.is_null() {
-            return Err(Error::DeviceLookupFailed);
+            let (code, message) = take_error(error);
+            return Err(Error::DeviceLookupFailed { code, message });
         }
 
         return Ok(Device::from_raw(device_ptr));
     }
 
     /// Returns the remote device with the specified host.
-    pub fn get_remote_device(&'a self, host: &str) -> Result<Device<'a>> {
+    pub fn get_remote_device(&self, host: &str) -> Result<Device<'a>> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
         let host_cstring = CString::new(host).map_err(|_| Error::CStringFailed)?;
 
@@ -101,14 +105,15 @@ impl<'a> DeviceManager<'a> {
         };
 
         if !error.is_null() {
-            return Err(Error::DeviceLookupFailed);
+            let (code, message) = take_error(error);
+            return Err(Error::DeviceLookupFailed { code, message });
         }
 
         return Ok(Device::from_raw(device_ptr));
     }
 
     /// Returns the local device.
-    pub fn get_local_device(&'a self) -> Result<Device<'a>> {
+    pub fn get_local_device(&self) -> Result<Device<'a>> {
         self.get_device_by_type(device::DeviceType::Local)
     }
 
@@ -123,7 +128,7 @@ impl<'a> DeviceManager<'a> {
     /// let device = device_manager.get_device_by_id(id).unwrap();
     /// assert_eq!(device.get_id(), id);
     ///
-    pub fn get_device_by_id(&'a self, device_id: &str) -> Result<Device<'a>> {
+    pub fn get_device_by_id(&self, device_id: &str) -> Result<Device<'a>> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
         let cstring = CString::new(device_id).unwrap();
 
@@ -138,7 +143,8 @@ impl<'a> DeviceManager<'a> {
         };
 
         if !error.is_null() {
-            return Err(Error::DeviceLookupFailed);
+            let (code, message) = take_error(error);
+            return Err(Error::DeviceLookupFailed { code, message });
         }
 
         return Ok(Device::from_raw(device_ptr));
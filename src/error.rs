@@ -4,38 +4,146 @@
  * Licence: wxWindows Library Licence, Version 3.1
  */
 
+use serde::Serialize;
+use std::ffi::CStr;
 use thiserror::Error;
 
+use crate::bind;
+
 /// Custom `Error` for Frida
 #[derive(Error, Debug)]
 pub enum Error {
     /// Failed to attach to a device.
-    #[error("Failed to attach")]
-    DeviceAttachError,
+    #[error("Failed to attach ({code}) {message}")]
+    DeviceAttachError {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
 
     /// Failled to lookup a device.
-    #[error("Failed to lookup device")]
-    DeviceLookupFailed,
+    #[error("Failed to lookup device ({code}) {message}")]
+    DeviceLookupFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
 
     /// Failled to enumerate a processes.
-    #[error("Failed to lookup device")]
-    ProcessesEnumerationFailed,
+    #[error("Failed to enumerate processes ({code}) {message}")]
+    ProcessesEnumerationFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// Failed to query the frontmost application
+    #[error("Failed to get the frontmost application ({code}) {message}")]
+    FrontmostApplicationFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// No application is in the foreground.
+    #[error("No frontmost application")]
+    NoFrontmostApplication,
+
+    /// Failed to enumerate applications
+    #[error("Failed to enumerate applications ({code}) {message}")]
+    ApplicationsEnumerationFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// Failed to look up a process
+    #[error("Failed to look up process ({code}) {message}")]
+    ProcessLookupFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// No process with the requested PID is running on the device.
+    #[error("No process with pid {pid}")]
+    ProcessNotFound {
+        /// Requested PID
+        pid: u32,
+    },
+
+    /// No process of the requested package is running on the device.
+    #[error("Package {package} is not running")]
+    PackageNotRunning {
+        /// Requested package name
+        package: String,
+    },
+
+    /// No loaded script with the requested id.
+    #[error("No script with id {id}")]
+    ScriptNotFound {
+        /// Requested script id
+        id: u64,
+    },
 
     /// Failed to detach a session.
-    #[error("Failed to detach the current session")]
-    SessionDetachError,
+    #[error("Failed to detach the current session ({code}) {message}")]
+    SessionDetachError {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// The session was detached by the target, e.g. because the process exited.
+    #[error("The session is detached")]
+    SessionDetached,
+
+    /// Failed to resume a persistent session.
+    #[error("Failed to resume the session ({code}) {message}")]
+    SessionResumeFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
 
     /// Failed to create a script in a session.
-    #[error("Failed to create the script")]
-    ScriptCreationError,
+    #[error("Failed to create the script ({code}) {message}")]
+    ScriptCreationError {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
 
     /// Failled to load a script in a session.
-    #[error("Failed to load the script")]
-    LoadingFailed,
+    #[error("Failed to load the script ({code}) {message}")]
+    LoadingFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
 
     /// Failed to unload a script in a session.
-    #[error("Failed to unload the script")]
-    UnloadingFailed,
+    #[error("Failed to unload the script ({code}) {message}")]
+    UnloadingFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// An asynchronous operation was dropped before completing.
+    #[error("The operation was cancelled")]
+    OperationCancelled,
 
     /// CString conversion failed.
     #[error("Failed to convert the string into CString")]
@@ -77,6 +185,42 @@ pub enum Error {
         message: String,
     },
 
+    /// Failed to enable or disable spawn gating
+    #[error("Failed to change spawn gating ({code}) {message}")]
+    SpawnGatingFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// Failed to enumerate pending spawns
+    #[error("Failed to enumerate pending spawns ({code}) {message}")]
+    PendingSpawnEnumerationFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// Failed to enable or disable child gating
+    #[error("Failed to change child gating ({code}) {message}")]
+    ChildGatingFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
+    /// Failed to enumerate pending children
+    #[error("Failed to enumerate pending children ({code}) {message}")]
+    PendingChildrenEnumerationFailed {
+        /// Error code
+        code: i32,
+        /// Error message
+        message: String,
+    },
+
     /// Failed to kill
     #[error("Failed to kill PID ({code}) {message}")]
     KillFailed {
@@ -90,6 +234,14 @@ pub enum Error {
     #[error("Unexpected RPC message received.")]
     RpcUnexpectedMessage,
 
+    /// The script did not answer an RPC call in time.
+    #[error("RPC call timed out")]
+    RpcTimeout,
+
+    /// The script was destroyed while an RPC call was pending.
+    #[error("Script was destroyed before replying")]
+    RpcScriptDestroyed,
+
     /// RPC JavaScript Error. The RPC communication was
     /// successful but there was an error in the JavaScript side
     #[error("Error on the JavaScript side: {message}")]
@@ -98,3 +250,207 @@ pub enum Error {
         message: String,
     },
 }
+
+impl Error {
+    /// Returns the `FridaError` code carried by the error, if it came from Frida.
+    pub fn code(&self) -> Option<i32> {
+        self.gerror()
+            .and_then(|(code, _)| (code >= 0).then_some(code))
+    }
+
+    /// Returns the message of the `GError` behind the error, as Frida reported it.
+    pub fn message(&self) -> Option<&str> {
+        self.gerror().map(|(_, message)| message)
+    }
+
+    /// Returns the code and message kept from a `GError`.
+    fn gerror(&self) -> Option<(i32, &str)> {
+        match self {
+            Self::DeviceAttachError { code, message }
+            | Self::DeviceLookupFailed { code, message }
+            | Self::ProcessesEnumerationFailed { code, message }
+            | Self::FrontmostApplicationFailed { code, message }
+            | Self::ApplicationsEnumerationFailed { code, message }
+            | Self::ProcessLookupFailed { code, message }
+            | Self::SessionDetachError { code, message }
+            | Self::SessionResumeFailed { code, message }
+            | Self::ScriptCreationError { code, message }
+            | Self::LoadingFailed { code, message }
+            | Self::UnloadingFailed { code, message }
+            | Self::InjectFailed { code, message }
+            | Self::DeviceQuerySystemParametersFailed { code, message }
+            | Self::SpawnFailed { code, message }
+            | Self::ResumeFailed { code, message }
+            | Self::SpawnGatingFailed { code, message }
+            | Self::PendingSpawnEnumerationFailed { code, message }
+            | Self::ChildGatingFailed { code, message }
+            | Self::PendingChildrenEnumerationFailed { code, message }
+            | Self::KillFailed { code, message } => Some((*code, message.as_str())),
+            _ => None,
+        }
+    }
+
+    /// Classifies the error by Frida's error domain.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ProcessNotFound { .. }
+            | Self::PackageNotRunning { .. }
+            | Self::NoFrontmostApplication => ErrorKind::ProcessNotFound,
+            Self::RpcTimeout => ErrorKind::TimedOut,
+            Self::CStringFailed => ErrorKind::InvalidArgument,
+            Self::ScriptNotFound { .. } => ErrorKind::ScriptNotFound,
+            Self::SessionDetached | Self::RpcScriptDestroyed => ErrorKind::InvalidOperation,
+            _ => self.code().map_or(ErrorKind::Other, ErrorKind::from),
+        }
+    }
+}
+
+/// Frida's error domain
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// frida-server is not running or cannot be reached
+    ServerNotRunning,
+    /// The program to spawn does not exist
+    ExecutableNotFound,
+    /// The program to spawn cannot be run on the device
+    ExecutableNotSupported,
+    /// No such process
+    ProcessNotFound,
+    /// No script is loaded under the requested id
+    ScriptNotFound,
+    /// The process did not respond in time
+    ProcessNotResponding,
+    /// A request argument, such as a script source, was rejected
+    InvalidArgument,
+    /// The operation is not allowed in the current state
+    InvalidOperation,
+    /// The operation is not permitted
+    PermissionDenied,
+    /// The address is already in use
+    AddressInUse,
+    /// The operation timed out
+    TimedOut,
+    /// The operation is not supported
+    NotSupported,
+    /// Unexpected data from the other end
+    Protocol,
+    /// The connection failed
+    Transport,
+    /// Not from Frida's error domain
+    Other,
+}
+
+impl From<i32> for ErrorKind {
+    fn from(code: i32) -> Self {
+        match code.try_into().unwrap_or(u32::MAX) {
+            bind::FridaError_FRIDA_ERROR_SERVER_NOT_RUNNING => Self::ServerNotRunning,
+            bind::FridaError_FRIDA_ERROR_EXECUTABLE_NOT_FOUND => Self::ExecutableNotFound,
+            bind::FridaError_FRIDA_ERROR_EXECUTABLE_NOT_SUPPORTED => Self::ExecutableNotSupported,
+            bind::FridaError_FRIDA_ERROR_PROCESS_NOT_FOUND => Self::ProcessNotFound,
+            bind::FridaError_FRIDA_ERROR_PROCESS_NOT_RESPONDING => Self::ProcessNotResponding,
+            bind::FridaError_FRIDA_ERROR_INVALID_ARGUMENT => Self::InvalidArgument,
+            bind::FridaError_FRIDA_ERROR_INVALID_OPERATION => Self::InvalidOperation,
+            bind::FridaError_FRIDA_ERROR_PERMISSION_DENIED => Self::PermissionDenied,
+            bind::FridaError_FRIDA_ERROR_ADDRESS_IN_USE => Self::AddressInUse,
+            bind::FridaError_FRIDA_ERROR_TIMED_OUT => Self::TimedOut,
+            bind::FridaError_FRIDA_ERROR_NOT_SUPPORTED => Self::NotSupported,
+            bind::FridaError_FRIDA_ERROR_PROTOCOL => Self::Protocol,
+            bind::FridaError_FRIDA_ERROR_TRANSPORT => Self::Transport,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Extracts the code and message of a `GError` and frees it.
+///
+/// The code is a `FridaError` value for Frida's own errors. Errors from other domains, such as
+/// GIO, get `-1` and the domain name in front of the message.
+pub(crate) fn take_error(error: *mut bind::GError) -> (i32, String) {
+    let mut message = unsafe { CStr::from_ptr((*error).message) }
+        .to_string_lossy()
+        .into_owned();
+    let domain = unsafe { (*error).domain };
+    let code = if domain == unsafe { bind::frida_error_quark() } {
+        unsafe { (*error).code }
+    } else {
+        let domain = unsafe { CStr::from_ptr(bind::_frida_g_quark_to_string(domain)) };
+        message = format!("{}: {}", domain.to_string_lossy(), message);
+        -1
+    };
+    unsafe { bind::_frida_g_error_free(error) };
+    (code, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_frida_error_codes_to_kinds() {
+        let kinds = [
+            (
+                bind::FridaError_FRIDA_ERROR_SERVER_NOT_RUNNING,
+                ErrorKind::ServerNotRunning,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_EXECUTABLE_NOT_FOUND,
+                ErrorKind::ExecutableNotFound,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_EXECUTABLE_NOT_SUPPORTED,
+                ErrorKind::ExecutableNotSupported,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_PROCESS_NOT_FOUND,
+                ErrorKind::ProcessNotFound,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_PROCESS_NOT_RESPONDING,
+                ErrorKind::ProcessNotResponding,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_INVALID_ARGUMENT,
+                ErrorKind::InvalidArgument,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_INVALID_OPERATION,
+                ErrorKind::InvalidOperation,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_PERMISSION_DENIED,
+                ErrorKind::PermissionDenied,
+            ),
+            (
+                bind::FridaError_FRIDA_ERROR_ADDRESS_IN_USE,
+                ErrorKind::AddressInUse,
+            ),
+            (bind::FridaError_FRIDA_ERROR_TIMED_OUT, ErrorKind::TimedOut),
+            (
+                bind::FridaError_FRIDA_ERROR_NOT_SUPPORTED,
+                ErrorKind::NotSupported,
+            ),
+            (bind::FridaError_FRIDA_ERROR_PROTOCOL, ErrorKind::Protocol),
+            (bind::FridaError_FRIDA_ERROR_TRANSPORT, ErrorKind::Transport),
+        ];
+        for (code, kind) in kinds {
+            assert_eq!(ErrorKind::from(code as i32), kind);
+        }
+    }
+
+    #[test]
+    fn maps_other_codes_to_other() {
+        // `take_error` gives errors from other domains -1.
+        assert_eq!(ErrorKind::from(-1), ErrorKind::Other);
+        assert_eq!(ErrorKind::from(i32::MAX), ErrorKind::Other);
+    }
+
+    #[test]
+    fn serializes_kinds_in_snake_case() {
+        assert_eq!(
+            serde_json::to_value(ErrorKind::ServerNotRunning).unwrap(),
+            "server_not_running"
+        );
+        assert_eq!(serde_json::to_value(ErrorKind::Other).unwrap(), "other");
+    }
+}
@@ -7,7 +7,10 @@
 
 //! Frida bindings for Rust.
 
-use std::ffi::CStr;
+use std::ffi::{c_void, CStr};
+use std::future::Future;
+
+use tokio::sync::oneshot;
 
 use crate::{bind, error};
 #[doc(hidden)]
@@ -67,8 +70,159 @@ impl Frida {
             bind::_frida_g_source_unref(source);
         }
     }
+
+    /// Runs an asynchronous Frida operation and resolves once it completes.
+    ///
+    /// `start` is invoked on the main frida context with the ready callback and user data to
+    /// hand to a `frida_*` function. `finish` is then called from that callback with the
+    /// `GAsyncResult`, and must call the matching `frida_*_finish` function. `object` is kept
+    /// alive until the operation completes, so the future does not borrow its wrapper.
+    pub(crate) fn run_async<T, S, F>(
+        &self,
+        object: bind::gpointer,
+        start: S,
+        finish: F,
+    ) -> impl Future<Output = Result<T>> + Send + 'static
+    where
+        T: Send + 'static,
+        S: FnOnce(bind::GAsyncReadyCallback, bind::gpointer) + Send + 'static,
+        F: FnOnce(*mut bind::GAsyncResult) -> Result<T> + Send + 'static,
+    {
+        struct Operation<T> {
+            object: SendPtr<std::ffi::c_void>,
+            finish: Box<dyn FnOnce(*mut bind::GAsyncResult) -> Result<T> + Send>,
+            tx: oneshot::Sender<Result<T>>,
+        }
+
+        unsafe extern "C" fn ready<T>(
+            _source_object: *mut bind::GObject,
+            res: *mut bind::GAsyncResult,
+            user_data: bind::gpointer,
+        ) {
+            let operation = Box::from_raw(user_data as *mut Operation<T>);
+            let result = (operation.finish)(res);
+            bind::_frida_g_object_unref(operation.object.get());
+            let _ = operation.tx.send(result);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let operation = Box::into_raw(Box::new(Operation {
+            object: SendPtr::new(unsafe { bind::_frida_g_object_ref(object) }),
+            finish: Box::new(finish),
+            tx,
+        }));
+        let user_data = SendPtr::new(operation as bind::gpointer);
+
+        self.schedule_on_main(move || start(Some(ready::<T>), user_data.get()));
+
+        async move { rx.await.unwrap_or(Err(error::Error::OperationCancelled)) }
+    }
+}
+
+/// Raw pointer that may be handed to the main frida context.
+///
+/// Frida objects are reference-counted GObjects and frida-core serializes all work on them
+/// onto its main context, so their pointers may cross threads.
+pub(crate) struct SendPtr<T>(*mut T);
+
+unsafe impl<T> Send for SendPtr<T> {}
+
+impl<T> Clone for SendPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
+impl<T> Copy for SendPtr<T> {}
+
+impl<T> SendPtr<T> {
+    pub(crate) fn new(ptr: *mut T) -> Self {
+        SendPtr(ptr)
+    }
+
+    /// Returns the pointer. Closures must go through this rather than the field, so that they
+    /// capture the whole `SendPtr`.
+    pub(crate) fn get(&self) -> *mut T {
+        self.0
+    }
+}
+
+/// Strong reference to a GObject that may be handed to the main frida context.
+///
+/// `*_async` wrappers take one on the options they are given and move it into the `start`
+/// closure of [`Frida::run_async`], so the caller may drop its options as soon as the wrapper
+/// returns. The reference is released with the closure, once the main context has started the
+/// call.
+pub(crate) struct ObjectRef<T>(SendPtr<T>);
+
+impl<T> ObjectRef<T> {
+    pub(crate) fn new(ptr: *mut T) -> Self {
+        unsafe { bind::_frida_g_object_ref(ptr as _) };
+        ObjectRef(SendPtr::new(ptr))
+    }
+
+    /// Returns the pointer, see [`SendPtr::get`].
+    pub(crate) fn get(&self) -> *mut T {
+        self.0.get()
+    }
+}
+
+impl<T> Drop for ObjectRef<T> {
+    fn drop(&mut self) {
+        unsafe { bind::_frida_g_object_unref(self.0.get() as _) }
+    }
+}
+
+/// Connects `callback` to `signal` of the GObject `instance`, returning the id to disconnect
+/// the handler with.
+///
+/// `callback` must match the signal's C signature, with `user_data` as its last argument.
+pub(crate) unsafe fn connect_signal<T>(
+    instance: *mut T,
+    signal: &CStr,
+    callback: *mut c_void,
+    user_data: *mut c_void,
+) -> bind::gulong {
+    let callback = Some(std::mem::transmute::<*mut c_void, unsafe extern "C" fn()>(
+        callback,
+    ));
+
+    bind::_frida_g_signal_connect_data(instance as _, signal.as_ptr(), callback, user_data, None, 0)
+}
+
+/// Disconnects the signal handlers `ids` of the GObject `instance` on the main context, where
+/// its signals are emitted, and only then drops `user_data`.
+///
+/// Disconnecting from another thread could race with an emission in progress, which would then
+/// read `user_data` after it is gone. Handlers may still run until the main context gets to it.
+pub(crate) fn disconnect_signals<T: 'static, D: Send + 'static>(
+    instance: *mut T,
+    ids: Vec<bind::gulong>,
+    user_data: D,
+) {
+    let instance = ObjectRef::new(instance);
+    crate::FRIDA.schedule_on_main(move || {
+        for id in ids {
+            unsafe { bind::_frida_g_signal_handler_disconnect(instance.get() as _, id) };
+        }
+        drop(user_data);
+    });
+}
+
+// The wrappers below only hold pointers to such objects, so they may be moved between threads
+// for the same reason as `SendPtr`.
+unsafe impl Send for crate::Device<'_> {}
+unsafe impl Send for crate::DeviceManager<'_> {}
+unsafe impl Send for crate::Process<'_> {}
+unsafe impl Send for crate::Session<'_> {}
+unsafe impl Send for crate::Script<'_> {}
+unsafe impl Send for crate::ProcessQueryOptions<'_> {}
+unsafe impl Send for crate::ProcessMatchOptions<'_> {}
+unsafe impl Send for crate::SpawnOptions<'_> {}
+unsafe impl Send for crate::ApplicationQueryOptions<'_> {}
+unsafe impl Send for crate::FrontmostQueryOptions<'_> {}
+unsafe impl Send for crate::SessionOptions<'_> {}
+
 impl Drop for Frida {
     fn drop(&mut self) {
         unsafe { bind::frida_deinit() };
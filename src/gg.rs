@@ -1,8 +1,8 @@
 use tokio::time::{sleep, Duration};
 
 use crate::{
-    configure, enumerate_processes, error, frida, get_pid, script, Channel, DeviceManager, Message,
-    MsgType, Pipe, ScriptHandler, FRIDA,
+    configure, enumerate_processes, error, frida, get_pid, script, Channel, Device, DeviceManager,
+    Message, MsgType, Pipe, ScriptHandler, FRIDA,
 };
 
 #[derive(Debug, Clone)]
@@ -32,8 +32,13 @@ pub async fn watchdog(_channel: Channel<Pipe<String>>) -> Result<(), Box<dyn std
     let watchdog = tokio::spawn({
         let mut inner_channel = inner_channel.clone();
         async move {
+            let mut remote: Option<Remote> = None;
             loop {
-                match get_pid(&gg.package).await {
+                if remote.as_ref().is_none_or(|remote| remote.device.is_lost()) {
+                    remote = Remote::connect(remote.take()).await;
+                }
+                let device = remote.as_ref().map(|remote| &remote.device);
+                match get_pid(device, &gg.package).await {
                     Some(pid) => {
                         let _ = inner_channel
                             .send(Pipe {
@@ -84,6 +89,35 @@ pub async fn watchdog(_channel: Channel<Pipe<String>>) -> Result<(), Box<dyn std
     Ok(())
 }
 
+/// Connection to frida-server kept across the watchdog's lookups.
+struct Remote {
+    // Declared before `_manager` so the device is released first.
+    device: Device<'static>,
+    /// Only held, dropping it closes the device.
+    _manager: DeviceManager<'static>,
+}
+
+impl Remote {
+    /// Connects to frida-server, closing `stale` first.
+    ///
+    /// Connecting and closing the device manager block, so they run on the blocking pool
+    /// rather than on the caller's worker.
+    async fn connect(stale: Option<Remote>) -> Option<Remote> {
+        tokio::task::spawn_blocking(move || {
+            drop(stale);
+            let manager = DeviceManager::obtain(&FRIDA);
+            let device = manager.get_remote_device("localhost").ok()?;
+            Some(Remote {
+                device,
+                _manager: manager,
+            })
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+}
+
 fn invoke(pid: u32) -> frida::Result<Handler> {
     let device_manager = DeviceManager::obtain(&FRIDA);
     let local_device = device_manager.get_remote_device("localhost")?;
@@ -93,7 +127,7 @@ fn invoke(pid: u32) -> frida::Result<Handler> {
     let session = local_device.attach(pid)?;
 
     if session.is_detached() {
-        return Err(error::Error::SessionDetachError);
+        return Err(error::Error::SessionDetached);
     }
 
     let mut script_option = script::ScriptOption::default();
@@ -0,0 +1,644 @@
+use std::collections::HashMap;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Upper bound for the request line plus headers.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Errors raised while reading an HTTP request from a socket.
+#[derive(Error, Debug)]
+pub enum RequestError {
+    /// The peer closed the connection before sending a request.
+    #[error("Connection closed")]
+    Closed,
+
+    /// The socket failed while reading.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The request could not be parsed.
+    #[error("Malformed request: {0}")]
+    Malformed(&'static str),
+
+    /// The request line and headers exceed [`MAX_HEAD_SIZE`].
+    #[error("Request headers too large")]
+    HeadTooLarge,
+
+    /// The body exceeds the configured maximum size.
+    #[error("Payload too large: limit is {limit} bytes")]
+    TooLarge {
+        /// Maximum accepted body size.
+        limit: usize,
+    },
+}
+
+impl RequestError {
+    /// HTTP status code to answer with, if the connection is still usable.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            RequestError::Closed | RequestError::Io(_) => None,
+            RequestError::Malformed(_) => Some(400),
+            RequestError::HeadTooLarge => Some(431),
+            RequestError::TooLarge { .. } => Some(413),
+        }
+    }
+}
+
+/// A parsed HTTP/1.1 request.
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the value of a header, `name` must be lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// Splits a `multipart/form-data` body into its parts.
+    ///
+    /// Returns `None` if the request is not multipart or the body is malformed.
+    pub fn multipart(&self) -> Option<Vec<Part>> {
+        let content_type = self.header("content-type")?;
+        if !content_type
+            .to_lowercase()
+            .starts_with("multipart/form-data")
+        {
+            return None;
+        }
+        let boundary = content_type
+            .split(';')
+            .filter_map(|param| param.trim().split_once('='))
+            .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+            .map(|(_, value)| value.trim_matches('"'))?;
+        let delimiter = format!("--{}", boundary).into_bytes();
+
+        let mut parts = Vec::new();
+        let mut rest = &self.body[find(&self.body, &delimiter)? + delimiter.len()..];
+        loop {
+            if rest.starts_with(b"--") {
+                return Some(parts);
+            }
+            rest = rest.strip_prefix(b"\r\n")?;
+
+            let head_end = find(rest, b"\r\n\r\n")?;
+            let head = String::from_utf8_lossy(&rest[..head_end]).to_string();
+            rest = &rest[head_end + 4..];
+
+            let end = find(rest, &[b"\r\n".as_slice(), &delimiter].concat())?;
+            let name = head
+                .split("\r\n")
+                .filter_map(|line| line.split_once(':'))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("content-disposition"))
+                .and_then(|(_, value)| {
+                    value
+                        .split(';')
+                        .filter_map(|param| param.trim().split_once('='))
+                        .find(|(key, _)| *key == "name")
+                        .map(|(_, name)| name.trim_matches('"').to_string())
+                })
+                .unwrap_or_default();
+
+            parts.push(Part {
+                name,
+                data: rest[..end].to_vec(),
+            });
+            rest = &rest[end + 2 + delimiter.len()..];
+        }
+    }
+}
+
+/// A single part of a `multipart/form-data` body.
+pub struct Part {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Reads one request from `stream`, honouring `Content-Length` and chunked transfer encoding.
+///
+/// Bodies larger than `max_body_size` are rejected with [`RequestError::TooLarge`] before
+/// they are buffered.
+pub async fn read_request<S>(stream: &mut S, max_body_size: usize) -> Result<Request, RequestError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut reader = Reader {
+        stream,
+        buf: Vec::with_capacity(READ_CHUNK),
+    };
+
+    let head_end = loop {
+        if let Some(pos) = find(&reader.buf, b"\r\n\r\n") {
+            break pos;
+        }
+        if reader.buf.len() > MAX_HEAD_SIZE {
+            return Err(RequestError::HeadTooLarge);
+        }
+        if reader.fill().await? == 0 {
+            return Err(if reader.buf.is_empty() {
+                RequestError::Closed
+            } else {
+                RequestError::Malformed("unexpected end of headers")
+            });
+        }
+    };
+
+    let head = String::from_utf8_lossy(&reader.buf[..head_end]).to_string();
+    reader.consume(head_end + 4);
+
+    let mut lines = head.split("\r\n");
+    let (method, target) = parse_method_and_path(lines.next().unwrap_or_default())
+        .ok_or(RequestError::Malformed("invalid request line"))?;
+
+    let mut headers = HashMap::new();
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(RequestError::Malformed("invalid header line"))?;
+        headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_query_params(query)),
+        None => (target.to_string(), HashMap::new()),
+    };
+
+    let mut request = Request {
+        method: method.to_string(),
+        path,
+        query,
+        headers,
+        body: Vec::new(),
+    };
+
+    let chunked = request
+        .header("transfer-encoding")
+        .map(|te| te.to_lowercase().contains("chunked"))
+        .unwrap_or(false);
+    let content_length = match request.header("content-length") {
+        Some(len) => Some(
+            len.parse::<usize>()
+                .map_err(|_| RequestError::Malformed("invalid content-length"))?,
+        ),
+        None => None,
+    };
+
+    if let Some(len) = content_length {
+        if !chunked && len > max_body_size {
+            return Err(RequestError::TooLarge {
+                limit: max_body_size,
+            });
+        }
+    }
+
+    if request
+        .header("expect")
+        .map(|e| e.eq_ignore_ascii_case("100-continue"))
+        .unwrap_or(false)
+    {
+        reader
+            .stream
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .await?;
+        reader.stream.flush().await?;
+    }
+
+    request.body = if chunked {
+        reader.read_chunked(max_body_size).await?
+    } else if let Some(len) = content_length {
+        reader.read_exact(len).await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(request)
+}
+
+struct Reader<'s, S> {
+    stream: &'s mut S,
+    buf: Vec<u8>,
+}
+
+impl<S> Reader<'_, S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    async fn fill(&mut self) -> std::io::Result<usize> {
+        let mut chunk = [0u8; READ_CHUNK];
+        let n = self.stream.read(&mut chunk).await?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.buf.drain(..n);
+    }
+
+    async fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, RequestError> {
+        while self.buf.len() < len {
+            if self.fill().await? == 0 {
+                return Err(RequestError::Malformed("body shorter than content-length"));
+            }
+        }
+        let body = self.buf[..len].to_vec();
+        self.consume(len);
+        Ok(body)
+    }
+
+    async fn read_line(&mut self) -> Result<String, RequestError> {
+        loop {
+            if let Some(pos) = find(&self.buf, b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buf[..pos]).to_string();
+                self.consume(pos + 2);
+                return Ok(line);
+            }
+            if self.buf.len() > MAX_HEAD_SIZE {
+                return Err(RequestError::HeadTooLarge);
+            }
+            if self.fill().await? == 0 {
+                return Err(RequestError::Malformed("unexpected end of chunked body"));
+            }
+        }
+    }
+
+    async fn read_chunked(&mut self, max_body_size: usize) -> Result<Vec<u8>, RequestError> {
+        let mut body = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| RequestError::Malformed("invalid chunk size"))?;
+
+            if size == 0 {
+                // Skip trailers up to the terminating empty line
+                while !self.read_line().await?.is_empty() {}
+                return Ok(body);
+            }
+            if size > max_body_size - body.len() {
+                return Err(RequestError::TooLarge {
+                    limit: max_body_size,
+                });
+            }
+
+            let chunk = self.read_exact(size + 2).await?;
+            if &chunk[size..] != b"\r\n" {
+                return Err(RequestError::Malformed("missing chunk terminator"));
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+}
+
+/// An HTTP response, always sent with `Connection: close`.
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub async fn write_to<S: AsyncWrite + Unpin>(&self, stream: &mut S) -> std::io::Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&self.body).await?;
+        stream.flush().await
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        410 => "Gone",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn parse_method_and_path(request_line: &str) -> Option<(&str, &str)> {
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?;
+    let path = parts.next()?;
+    Some((method, path))
+}
+
+fn parse_query_params(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|param| {
+            let mut split = param.splitn(2, '=');
+            let key = percent_decode(split.next()?);
+            let value = percent_decode(split.next()?);
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes and `+` in a query component, keeping malformed escapes as they are.
+fn percent_decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let escape = bytes
+                    .get(i + 1..i + 3)
+                    .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match escape {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::ReadBuf;
+
+    /// Stream that hands out one queued slice per read and records what is written to it.
+    struct Feed {
+        reads: VecDeque<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Feed {
+        fn new(reads: &[&[u8]]) -> Self {
+            Feed {
+                reads: reads.iter().map(|read| read.to_vec()).collect(),
+                written: Vec::new(),
+            }
+        }
+    }
+
+    impl AsyncRead for Feed {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if let Some(mut read) = self.reads.pop_front() {
+                let rest = read.split_off(read.len().min(buf.remaining()));
+                buf.put_slice(&read);
+                if !rest.is_empty() {
+                    self.reads.push_front(rest);
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for Feed {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.written.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn read(reads: &[&[u8]], max_body_size: usize) -> Result<Request, RequestError> {
+        read_request(&mut Feed::new(reads), max_body_size).await
+    }
+
+    #[tokio::test]
+    async fn reads_content_length_body() {
+        let request = read(
+            &[b"POST /scripts/1/post?pid=42 HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello"],
+            1024,
+        )
+        .await
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/scripts/1/post");
+        assert_eq!(request.query["pid"], "42");
+        assert_eq!(request.header("content-length"), Some("5"));
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn reads_head_and_body_split_across_reads() {
+        let request = read(
+            &[
+                b"POST / HT",
+                b"TP/1.1\r\nConte",
+                b"nt-Length: 11\r",
+                b"\n\r\nhello",
+                b" world",
+            ],
+            1024,
+        )
+        .await
+        .unwrap();
+        assert_eq!(request.header("content-length"), Some("11"));
+        assert_eq!(request.body, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_body_with_extensions_and_trailers() {
+        let request = read(
+            &[
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                b"5;ext=1\r\nhello\r\n6\r\n wo",
+                b"rld\r\n0\r\nX-Trailer: 1\r\n\r\n",
+            ],
+            1024,
+        )
+        .await
+        .unwrap();
+        assert_eq!(request.body, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn rejects_content_length_over_limit() {
+        let err = read(
+            &[b"POST / HTTP/1.1\r\nContent-Length: 4194305\r\n\r\n"],
+            crate::DEFAULT_MAX_BODY_SIZE,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(err, RequestError::TooLarge { limit } if limit == crate::DEFAULT_MAX_BODY_SIZE)
+        );
+        assert_eq!(err.status(), Some(413));
+    }
+
+    #[tokio::test]
+    async fn rejects_chunked_body_over_limit() {
+        let err = read(
+            &[b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n8\r\n"],
+            4,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status(), Some(413));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_requests() {
+        for reads in [
+            &[b"GARBAGE\r\n\r\n".as_slice()][..],
+            &[b"GET / HTTP/1.1\r\nno-colon\r\n\r\n"],
+            &[b"POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n"],
+            &[b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"],
+            &[b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"],
+            &[b"GET / HTTP/1.1\r\n"],
+        ] {
+            let err = read(reads, 1024).await.unwrap_err();
+            assert_eq!(err.status(), Some(400), "{:?}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_closed_connection_and_oversized_head() {
+        assert!(matches!(read(&[], 1024).await, Err(RequestError::Closed)));
+
+        let head = vec![b'a'; MAX_HEAD_SIZE + 1];
+        let err = read(&[b"GET / HTTP/1.1\r\nX: ", &head], 1024)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(431));
+    }
+
+    #[tokio::test]
+    async fn answers_expect_continue() {
+        let mut feed = Feed::new(&[
+            b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\n",
+            b"ok",
+        ]);
+        let request = read_request(&mut feed, 1024).await.unwrap();
+        assert_eq!(request.body, b"ok");
+        assert_eq!(feed.written, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    fn multipart_request(content_type: &str, body: &[u8]) -> Request {
+        Request {
+            method: "POST".into(),
+            path: "/scripts/1/post".into(),
+            query: HashMap::new(),
+            headers: HashMap::from([("content-type".into(), content_type.into())]),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn splits_multipart_parts() {
+        let request = multipart_request(
+            "multipart/form-data; boundary=\"XyZ\"",
+            b"preamble\r\n--XyZ\r\n\
+              Content-Disposition: form-data; name=\"message\"\r\n\r\n\
+              {\"type\":\"ping\"}\r\n--XyZ\r\n\
+              Content-Disposition: form-data; name=\"data\"; filename=\"blob.bin\"\r\n\
+              Content-Type: application/octet-stream\r\n\r\n\
+              \x00\r\n--X\xff\r\n--XyZ--\r\n",
+        );
+        let parts = request.multipart().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "message");
+        assert_eq!(parts[0].data, b"{\"type\":\"ping\"}");
+        assert_eq!(parts[1].name, "data");
+        assert_eq!(parts[1].data, b"\x00\r\n--X\xff");
+    }
+
+    #[test]
+    fn rejects_non_multipart_and_malformed_bodies() {
+        let part = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b--";
+        assert!(multipart_request("application/json", part)
+            .multipart()
+            .is_none());
+        assert!(multipart_request("multipart/form-data", part)
+            .multipart()
+            .is_none());
+        assert!(
+            multipart_request("multipart/form-data; boundary=b", b"no delimiter")
+                .multipart()
+                .is_none()
+        );
+        assert!(
+            multipart_request("multipart/form-data; boundary=b", &part[..part.len() - 5])
+                .multipart()
+                .is_none()
+        );
+
+        let parts = multipart_request("Multipart/Form-Data; Boundary=b", part)
+            .multipart()
+            .unwrap();
+        assert_eq!(parts[0].name, "a");
+        assert_eq!(parts[0].data, b"1");
+    }
+
+    #[test]
+    fn decodes_query_params() {
+        let query = parse_query_params("name=my+script%21&path=%2Fdata%2Flocal&bad=%zz%+F%4&flag");
+        assert_eq!(query["name"], "my script!");
+        assert_eq!(query["path"], "/data/local");
+        assert_eq!(query["bad"], "%zz% F%4");
+        assert!(!query.contains_key("flag"));
+    }
+}
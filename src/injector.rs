@@ -7,6 +7,7 @@ use std::path::Path;
 use std::os::unix::ffi::OsStrExt;
 
 use crate::bind;
+use crate::error::take_error;
 
 /// Local library injector
 ///
@@ -173,11 +174,7 @@ impl<'a> Inject for Injector<'a> {
             )
         };
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::InjectFailed { code, message });
         }
 
@@ -216,11 +213,7 @@ impl<'a> Inject for Injector<'a> {
         };
 
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::InjectFailed { code, message });
         }
 
@@ -271,11 +264,7 @@ impl<'a> Inject for Device<'a> {
             )
         };
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::InjectFailed { code, message });
         }
 
@@ -314,11 +303,7 @@ impl<'a> Inject for Device<'a> {
         };
 
         if !error.is_null() {
-            let message = unsafe { CString::from_raw((*error).message) }
-                .into_string()
-                .map_err(|_| Error::CStringFailed)?;
-            let code = unsafe { (*error).code };
-
+            let (code, message) = take_error(error);
             return Err(Error::InjectFailed { code, message });
         }
 
@@ -2,6 +2,7 @@
 mod bindings {
     include!("./bind.rs");
 }
+mod application;
 mod bind;
 mod config;
 mod define;
@@ -10,13 +11,17 @@ mod device_manager;
 mod error;
 mod frida;
 mod gg;
+mod http;
 mod injector;
+mod message_buffer;
 mod process;
+mod registry;
 mod script;
 mod server;
 mod session;
 mod variant;
 
+pub use application::*;
 pub use config::*;
 pub use define::*;
 pub use device::*;
@@ -27,6 +32,7 @@ pub use frida::*;
 pub use process::*;
 pub use script::*;
 pub use session::*;
+pub use variant::*;
 
 use std::sync::LazyLock;
 
@@ -0,0 +1,136 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::{Message, MessageSend, ScriptHandler};
+
+/// Bounded log of the messages a script has emitted.
+///
+/// Every message gets a sequence number starting at 1, so pollers can ask for what they have
+/// not seen yet. Once full, the oldest messages are dropped.
+#[derive(Clone)]
+pub struct MessageBuffer {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    messages: VecDeque<Value>,
+    last_seq: u64,
+    capacity: usize,
+}
+
+impl MessageBuffer {
+    pub fn new(capacity: usize) -> Self {
+        MessageBuffer {
+            inner: Arc::new(Mutex::new(Inner {
+                messages: VecDeque::with_capacity(capacity),
+                last_seq: 0,
+                capacity,
+            })),
+        }
+    }
+
+    /// Appends a message, evicting the oldest one when the buffer is full.
+    ///
+    /// Binary data attached to a send message is stored base64-encoded under `data`.
+    pub fn push(&self, message: &Message) {
+        let Ok(Value::Object(mut entry)) = serde_json::to_value(message) else {
+            return;
+        };
+        if let Message::Send(MessageSend {
+            data: Some(data), ..
+        }) = message
+        {
+            entry.insert("data".into(), json!(BASE64.encode(data)));
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.last_seq += 1;
+        entry.insert("seq".into(), json!(inner.last_seq));
+
+        if inner.messages.len() == inner.capacity {
+            inner.messages.pop_front();
+        }
+        inner.messages.push_back(Value::Object(entry));
+    }
+
+    /// Returns the buffered messages with a sequence number above `seq`, along with the last
+    /// sequence number handed out.
+    pub fn since(&self, seq: u64) -> (Vec<Value>, u64) {
+        let inner = self.inner.lock().unwrap();
+        let messages = inner
+            .messages
+            .iter()
+            .filter(|message| message["seq"].as_u64().unwrap_or_default() > seq)
+            .cloned()
+            .collect();
+        (messages, inner.last_seq)
+    }
+}
+
+impl ScriptHandler for MessageBuffer {
+    fn on_message(&mut self, message: &Message) {
+        self.push(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageLog, MessageLogLevel};
+
+    fn log(payload: &str) -> Message {
+        Message::Log(MessageLog {
+            level: MessageLogLevel::Info,
+            payload: payload.to_string(),
+        })
+    }
+
+    fn payloads(messages: &[Value]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message["payload"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn numbers_messages_and_returns_those_after_seq() {
+        let buffer = MessageBuffer::new(8);
+        for payload in ["a", "b", "c"] {
+            buffer.push(&log(payload));
+        }
+
+        let (messages, last) = buffer.since(0);
+        assert_eq!(last, 3);
+        assert_eq!(payloads(&messages), ["a", "b", "c"]);
+        assert_eq!(messages[0]["seq"], 1);
+        assert_eq!(messages[0]["type"], "log");
+        assert_eq!(messages[0]["level"], "info");
+
+        let (messages, last) = buffer.since(2);
+        assert_eq!(last, 3);
+        assert_eq!(payloads(&messages), ["c"]);
+
+        let (messages, last) = buffer.since(3);
+        assert_eq!(last, 3);
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn drops_oldest_messages_when_full() {
+        let buffer = MessageBuffer::new(3);
+        for payload in ["a", "b", "c", "d", "e"] {
+            buffer.push(&log(payload));
+        }
+
+        let (messages, last) = buffer.since(0);
+        assert_eq!(last, 5);
+        assert_eq!(payloads(&messages), ["c", "d", "e"]);
+        assert_eq!(messages[0]["seq"], 3);
+
+        // A poller that fell behind only gets what is still buffered.
+        let (messages, _) = buffer.since(1);
+        assert_eq!(payloads(&messages), ["c", "d", "e"]);
+    }
+}
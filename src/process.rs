@@ -5,8 +5,14 @@
  */
 use crate::bind;
 
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
+use std::future::Future;
 use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::variant::{hash_table_from_map, hash_table_to_map, Variant};
+use crate::Scope;
 
 /// Process management in Frida.
 pub struct Process<'a> {
@@ -34,6 +40,13 @@ impl<'a> Process<'a> {
     pub fn get_pid(&self) -> u32 {
         unsafe { bind::frida_process_get_pid(self.process_ptr) }
     }
+
+    /// Returns the extra details of the process, filled in according to the [`Scope`] of the
+    /// query: `path`, `user`, `ppid` and `started` from [`Scope::Metadata`], plus `icons` at
+    /// [`Scope::Full`].
+    pub fn get_parameters(&self) -> HashMap<String, Variant> {
+        unsafe { hash_table_to_map(bind::frida_process_get_parameters(self.process_ptr)) }
+    }
 }
 
 impl<'a> Drop for Process<'a> {
@@ -42,6 +55,188 @@ impl<'a> Drop for Process<'a> {
     }
 }
 
+/// Process enumeration options
+pub struct ProcessQueryOptions<'a> {
+    pub(crate) options_ptr: *mut bind::FridaProcessQueryOptions,
+    phantom: PhantomData<&'a bind::FridaProcessQueryOptions>,
+}
+
+impl<'a> ProcessQueryOptions<'a> {
+    /// Create options that match every process
+    pub fn new() -> Self {
+        Self {
+            options_ptr: unsafe { bind::frida_process_query_options_new() },
+            phantom: PhantomData,
+        }
+    }
+
+    /// Only return the processes with these pids
+    pub fn select_pids<L: IntoIterator<Item = u32>>(self, pids: L) -> Self {
+        for pid in pids {
+            unsafe { bind::frida_process_query_options_select_pid(self.options_ptr, pid) }
+        }
+        self
+    }
+
+    /// Set how much detail is returned
+    pub fn scope(self, scope: Scope) -> Self {
+        unsafe { bind::frida_process_query_options_set_scope(self.options_ptr, scope as _) }
+        self
+    }
+}
+
+impl<'a> Default for ProcessQueryOptions<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Drop for ProcessQueryOptions<'a> {
+    fn drop(&mut self) {
+        unsafe { bind::frida_unref(self.options_ptr as _) }
+    }
+}
+
+/// Process lookup options
+pub struct ProcessMatchOptions<'a> {
+    pub(crate) options_ptr: *mut bind::FridaProcessMatchOptions,
+    phantom: PhantomData<&'a bind::FridaProcessMatchOptions>,
+}
+
+impl<'a> ProcessMatchOptions<'a> {
+    /// Create options that look up the process once, with minimal detail
+    pub fn new() -> Self {
+        Self {
+            options_ptr: unsafe { bind::frida_process_match_options_new() },
+            phantom: PhantomData,
+        }
+    }
+
+    /// Keep waiting up to `timeout` for a matching process to appear
+    pub fn timeout(self, timeout: Duration) -> Self {
+        let ms = timeout.as_millis().try_into().unwrap_or(i32::MAX);
+        unsafe { bind::frida_process_match_options_set_timeout(self.options_ptr, ms) }
+        self
+    }
+
+    /// Set how much detail is returned
+    pub fn scope(self, scope: Scope) -> Self {
+        unsafe { bind::frida_process_match_options_set_scope(self.options_ptr, scope as _) }
+        self
+    }
+}
+
+impl<'a> Default for ProcessMatchOptions<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Drop for ProcessMatchOptions<'a> {
+    fn drop(&mut self) {
+        unsafe { bind::frida_unref(self.options_ptr as _) }
+    }
+}
+
+/// A process held by spawn gating, waiting to be resumed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spawn {
+    /// Process ID of the spawned process.
+    pub pid: u32,
+    /// Application identifier, `None` for plain programs.
+    pub identifier: Option<String>,
+}
+
+impl Spawn {
+    pub(crate) fn from_raw(spawn_ptr: *mut bind::FridaSpawn) -> Self {
+        Spawn {
+            pid: unsafe { bind::frida_spawn_get_pid(spawn_ptr) },
+            identifier: owned_str(unsafe { bind::frida_spawn_get_identifier(spawn_ptr) }),
+        }
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+/// How a gated child process came to be
+pub enum ChildOrigin {
+    /// Created by `fork()`
+    Fork = bind::FridaChildOrigin_FRIDA_CHILD_ORIGIN_FORK as _,
+
+    /// Replaced its image with `exec()`
+    Exec = bind::FridaChildOrigin_FRIDA_CHILD_ORIGIN_EXEC as _,
+
+    /// Started by `posix_spawn()` or an equivalent
+    Spawn = bind::FridaChildOrigin_FRIDA_CHILD_ORIGIN_SPAWN as _,
+}
+
+impl From<bind::FridaChildOrigin> for ChildOrigin {
+    fn from(value: bind::FridaChildOrigin) -> Self {
+        match value {
+            bind::FridaChildOrigin_FRIDA_CHILD_ORIGIN_FORK => Self::Fork,
+            bind::FridaChildOrigin_FRIDA_CHILD_ORIGIN_EXEC => Self::Exec,
+            _ => Self::Spawn,
+        }
+    }
+}
+
+/// A child process held by child gating, waiting to be resumed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Child {
+    /// Process ID of the child.
+    pub pid: u32,
+    /// Process ID of the gated process that created it.
+    pub parent_pid: u32,
+    /// How the child was created.
+    pub origin: ChildOrigin,
+    /// Application identifier, if the child is an application process.
+    pub identifier: Option<String>,
+    /// Path of the executable, if known.
+    pub path: Option<String>,
+    /// Argument vector, if known.
+    pub argv: Option<Vec<String>>,
+    /// Environment as `KEY=VALUE` entries, if known.
+    pub envp: Option<Vec<String>>,
+}
+
+impl Child {
+    pub(crate) fn from_raw(child_ptr: *mut bind::FridaChild) -> Self {
+        let mut argc = 0;
+        let argv = unsafe { bind::frida_child_get_argv(child_ptr, &mut argc) };
+        let mut envc = 0;
+        let envp = unsafe { bind::frida_child_get_envp(child_ptr, &mut envc) };
+        Child {
+            pid: unsafe { bind::frida_child_get_pid(child_ptr) },
+            parent_pid: unsafe { bind::frida_child_get_parent_pid(child_ptr) },
+            origin: unsafe { bind::frida_child_get_origin(child_ptr) }.into(),
+            identifier: owned_str(unsafe { bind::frida_child_get_identifier(child_ptr) }),
+            path: owned_str(unsafe { bind::frida_child_get_path(child_ptr) }),
+            argv: owned_strv(argv, argc),
+            envp: owned_strv(envp, envc),
+        }
+    }
+}
+
+/// Copies a nullable C string.
+fn owned_str(ptr: *const bind::gchar) -> Option<String> {
+    (!ptr.is_null()).then(|| {
+        unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned()
+    })
+}
+
+/// Copies a nullable string vector of `len` entries.
+fn owned_strv(ptr: *mut *mut bind::gchar, len: bind::gint) -> Option<Vec<String>> {
+    if ptr.is_null() {
+        return None;
+    }
+    let strings = (0..len as usize)
+        .filter_map(|i| owned_str(unsafe { *ptr.add(i) }))
+        .collect();
+    Some(strings)
+}
+
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 /// Standard I/O routing for a spawn
@@ -149,6 +344,16 @@ impl<'a> SpawnOptions<'a> {
         self
     }
 
+    /// Set auxiliary options, e.g. `"uid"` on Android
+    pub fn aux(self, aux: &HashMap<String, Variant>) -> Self {
+        unsafe {
+            let ht = hash_table_from_map(aux);
+            bind::frida_spawn_options_set_aux(self.options_ptr, ht);
+            bind::_frida_g_hash_table_unref(ht);
+        }
+        self
+    }
+
     /// Set the Standard I/O handling
     pub fn stdio(self, stdio: SpawnStdio) -> Self {
         unsafe { bind::frida_spawn_options_set_stdio(self.options_ptr, stdio as _) }
@@ -183,26 +388,67 @@ pub fn enumerate_processes(device: &crate::Device) -> crate::Result<Vec<(u32, St
     Ok(result)
 }
 
-pub async fn get_pid(app_package: &str) -> Option<u32> {
-    let output = tokio::process::Command::new("ps")
-        .arg("-A")
-        .stdout(std::process::Stdio::piped())
-        .output().await
-        .ok()?;
-
-    let output_str = String::from_utf8_lossy(&output.stdout);
-
-    for line in output_str.lines() {
-        if line.contains(app_package) {
-            let parts: Vec<&str> = line.split_whitespace().collect();
+/// Returns the pid of the running process of `name` on `device`, `None` if it is not running.
+///
+/// `name` is either a package id like `com.foo`, looked up among the applications since
+/// frida-server names Android processes after the application's label, or the process name of
+/// another component like `com.foo:remote`.
+pub fn find_pid_async(
+    device: &crate::Device<'static>,
+    name: &str,
+) -> impl Future<Output = crate::Result<Option<u32>>> + Send + 'static {
+    let device = device.clone();
+    let name = name.to_string();
+    async move {
+        if name.contains(':') {
+            let find = device.find_process_by_name_async(&name, &ProcessMatchOptions::new());
+            return Ok(find.await?.map(|process| process.get_pid()));
+        }
+        let options = crate::ApplicationQueryOptions::new().select_identifiers([&name])?;
+        let enumerate = device.enumerate_applications_async(&options);
+        drop(options);
+        let applications = enumerate.await?;
+        Ok(applications
+            .into_iter()
+            .find_map(|application| application.pid))
+    }
+}
 
-            if let Some(pid_str) = parts.get(1) {
-                if let Ok(pid) = pid_str.parse::<u32>() {
-                    return Some(pid);
-                }
-            }
+/// Returns the pid of the running process of `name`, see [`find_pid_async`].
+///
+/// Scans `/proc` when there is no `device`, or frida-server cannot be asked or does not know
+/// `name`.
+pub fn get_pid(
+    device: Option<&crate::Device<'static>>,
+    name: &str,
+) -> impl Future<Output = Option<u32>> + Send + 'static {
+    let lookup = device.map(|device| find_pid_async(device, name));
+    let name = name.to_string();
+    async move {
+        let found = match lookup {
+            Some(lookup) => lookup.await.ok().flatten(),
+            None => None,
+        };
+        match found {
+            Some(pid) => Some(pid),
+            None => find_pid_in_proc(&name).await,
         }
     }
+}
 
+/// Scans `/proc/<pid>/cmdline` for a process whose `argv[0]` is exactly `name`.
+async fn find_pid_in_proc(name: &str) -> Option<u32> {
+    let mut entries = tokio::fs::read_dir("/proc").await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        let Ok(cmdline) = tokio::fs::read(entry.path().join("cmdline")).await else {
+            continue;
+        };
+        if cmdline.split(|&b| b == 0).next() == Some(name.as_bytes()) {
+            return Some(pid);
+        }
+    }
     None
 }
@@ -0,0 +1,865 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+
+use crate::message_buffer::MessageBuffer;
+use crate::{
+    find_pid_async, Child, CrashReport, DetachReason, Device, DeviceManager, Error,
+    FrontmostQueryOptions, Message, MessageFilter, Process, ProcessMatchOptions,
+    ProcessQueryOptions, Realm, Result, Script, ScriptHandler, ScriptOption, Session,
+    SessionHandler, SessionOptions, Spawn, SpawnOptions, Subscription, Variant, FRIDA,
+};
+
+/// Number of messages kept per script for `GET /scripts/{id}/messages`.
+const MESSAGE_BUFFER_CAPACITY: usize = 512;
+
+/// Identifier handed back to HTTP callers for a loaded script.
+pub type ScriptId = u64;
+
+/// A script kept loaded in its target process, together with the session that owns it.
+pub struct LoadedScript {
+    pub pid: u32,
+    pub package: String,
+    pub name: String,
+    /// Realm the session instruments, kept when the script is re-attached.
+    pub realm: Realm,
+    pub loaded_at: SystemTime,
+    /// Source the script was last loaded with, replayed into followed children.
+    pub source: String,
+    pub messages: MessageBuffer,
+    /// Whether the target was named by package, so the script follows the package into its
+    /// next process once this one is gone.
+    pub by_package: bool,
+    detached: DetachedState,
+    /// Only held, dropping them unsubscribes the entry's observers.
+    _subscriptions: Vec<Subscription>,
+    // Declared before `session` so the script is released first.
+    script: Script<'static>,
+    session: Session<'static>,
+}
+
+impl LoadedScript {
+    /// Returns why and when the script's session went away, `None` while it is alive or only
+    /// interrupted.
+    pub fn detached(&self) -> Option<Detached> {
+        self.detached.get()
+    }
+
+    /// Unloads the script and detaches its session, unless the session is already gone.
+    async fn unload(self) -> Result<()> {
+        if !self.session.is_detached() {
+            let unload = self.script.unload_async();
+            unload.await?;
+            let detach = self.session.detach_async();
+            detach.await?;
+        }
+        Ok(())
+    }
+}
+
+/// Why and when the session of a script was detached.
+#[derive(Debug, Clone)]
+pub struct Detached {
+    pub reason: DetachReason,
+    /// Set when the process crashed.
+    pub crash: Option<CrashReport>,
+    pub at: SystemTime,
+}
+
+/// Daemon-wide registry of loaded scripts.
+///
+/// Owns the device manager and the frida-server device, so sessions stay attached for as long
+/// as their entry is kept here.
+pub struct Registry {
+    scripts: HashMap<ScriptId, LoadedScript>,
+    next_id: ScriptId,
+    device: Option<Device<'static>>,
+    /// Whether spawn gating is enabled on the current `device`.
+    spawn_gating: Arc<AtomicBool>,
+    /// Woken by every `device` when spawn or child gating holds a new process.
+    gated: Arc<Notify>,
+    /// Woken whenever a script is loaded, so a watcher can check [`Registry::watches`] again.
+    watched: Arc<Notify>,
+    /// Whether new sessions enable child gating so their scripts follow into children.
+    follow_children: bool,
+    /// Whether a missing pid resolves to the frontmost application.
+    frontmost_by_default: bool,
+    /// How long sessions survive a dropped connection to frida-server.
+    persist_timeout: Duration,
+    manager: DeviceManager<'static>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry {
+            scripts: HashMap::new(),
+            next_id: 1,
+            device: None,
+            spawn_gating: Arc::new(AtomicBool::new(false)),
+            gated: Arc::new(Notify::new()),
+            watched: Arc::new(Notify::new()),
+            follow_children: false,
+            frontmost_by_default: false,
+            persist_timeout: Duration::ZERO,
+            manager: DeviceManager::obtain(&FRIDA),
+        }
+    }
+
+    /// Sets whether sessions attached from now on hold their children, so [`adopt`] can load
+    /// the parent's scripts into them before they run.
+    ///
+    /// [`adopt`]: Registry::adopt
+    pub fn follow_children(mut self, follow: bool) -> Self {
+        self.follow_children = follow;
+        self
+    }
+
+    pub fn follows_children(&self) -> bool {
+        self.follow_children
+    }
+
+    /// Sets whether requests without a pid target the application in the foreground.
+    pub fn frontmost_by_default(mut self, frontmost: bool) -> Self {
+        self.frontmost_by_default = frontmost;
+        self
+    }
+
+    pub fn targets_frontmost(&self) -> bool {
+        self.frontmost_by_default
+    }
+
+    /// Sets how long sessions attached from now on keep their scripts after the connection to
+    /// frida-server drops, waiting for [`resume_interrupted`](Registry::resume_interrupted).
+    pub fn persist_timeout(mut self, timeout: Duration) -> Self {
+        self.persist_timeout = timeout;
+        self
+    }
+
+    /// Returns the pid of the application in the foreground.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn frontmost_pid(&mut self) -> Result<impl Future<Output = Result<u32>> + Send + 'static> {
+        let frontmost = self
+            .device()?
+            .get_frontmost_application_async(&FrontmostQueryOptions::new());
+        Ok(async move {
+            frontmost
+                .await?
+                .and_then(|application| application.pid)
+                .ok_or(Error::NoFrontmostApplication)
+        })
+    }
+
+    /// Returns the frida-server device, reconnecting if it was lost.
+    pub fn device(&mut self) -> Result<&Device<'static>> {
+        if self.device.as_ref().is_none_or(|device| device.is_lost()) {
+            let mut device = self.manager.get_remote_device("localhost")?;
+            device.notify_gated(self.gated.clone());
+            self.device = Some(device);
+            self.spawn_gating = Arc::new(AtomicBool::new(false));
+        }
+        Ok(self.device.as_ref().unwrap())
+    }
+
+    /// Attaches to the running process of `package` and loads `source` into it.
+    ///
+    /// Once that process is gone, [`reattach`](Registry::reattach) loads the script into the
+    /// package's next process under the same id.
+    pub fn load_package(
+        &mut self,
+        package: &str,
+        realm: Realm,
+        source: &str,
+        name: Option<&str>,
+    ) -> Result<impl Future<Output = Result<Injected>> + Send + 'static> {
+        let find = self.package_pid(package)?;
+        let loader = self.loader()?;
+        let id = self.next_id();
+        let (package, source, name) = (
+            package.to_string(),
+            source.to_string(),
+            script_name(id, name),
+        );
+        Ok(async move {
+            let pid = find.await?;
+            let spec = Spec::new(pid, realm, package, source, name).by_package();
+            loader.inject(id, spec).await
+        })
+    }
+
+    /// Returns the pid of the running process of `package`.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn package_pid(
+        &mut self,
+        package: &str,
+    ) -> Result<impl Future<Output = Result<u32>> + Send + 'static> {
+        let find = find_pid_async(self.device()?, package);
+        let package = package.to_string();
+        Ok(async move { find.await?.ok_or(Error::PackageNotRunning { package }) })
+    }
+
+    /// Returns the system parameters of the device.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn system_parameters(
+        &mut self,
+    ) -> Result<impl Future<Output = Result<HashMap<String, Variant>>> + Send + 'static> {
+        Ok(self.device()?.query_system_parameters_async())
+    }
+
+    /// Lists the processes on the device matching `options`.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn processes(
+        &mut self,
+        options: ProcessQueryOptions<'static>,
+    ) -> Result<impl Future<Output = Result<Vec<Process<'static>>>> + Send + 'static> {
+        Ok(self.device()?.enumerate_processes_with_async(&options))
+    }
+
+    /// Attaches to `pid` and loads `source` into it.
+    ///
+    /// With [`Realm::Emulated`] the script runs in the emulated realm, e.g. the ARM code an
+    /// x86_64 Android emulator translates through its native bridge.
+    pub fn load(
+        &mut self,
+        pid: u32,
+        realm: Realm,
+        source: &str,
+        name: Option<&str>,
+    ) -> Result<impl Future<Output = Result<Injected>> + Send + 'static> {
+        let process = self
+            .device()?
+            .get_process_by_pid_async(pid, &ProcessMatchOptions::new());
+        let loader = self.loader()?;
+        let id = self.next_id();
+        let (source, name) = (source.to_string(), script_name(id, name));
+        Ok(async move {
+            let package = process.await?.get_name().to_string();
+            loader
+                .inject(id, Spec::new(pid, realm, package, source, name))
+                .await
+        })
+    }
+
+    /// Spawns `program` suspended, loads `source` into it and only then resumes it, so the
+    /// script sees the process from its first instruction.
+    ///
+    /// The process is killed if the script cannot be loaded or the process cannot be resumed.
+    pub fn spawn(
+        &mut self,
+        program: &str,
+        options: SpawnOptions<'static>,
+        source: &str,
+        name: Option<&str>,
+    ) -> Result<impl Future<Output = Result<Injected>> + Send + 'static> {
+        let spawn = self.device()?.spawn_async(program, &options);
+        let loader = self.loader()?;
+        let id = self.next_id();
+        let (program, source, name) = (
+            program.to_string(),
+            source.to_string(),
+            script_name(id, name),
+        );
+        Ok(async move {
+            let pid = spawn.await?;
+            let spec = Spec::new(pid, Realm::Native, program, source, name).by_package();
+            loader.instrument(id, spec).await
+        })
+    }
+
+    /// Returns the notifier woken whenever spawn or child gating holds a new process.
+    ///
+    /// It survives reconnects, but a lost device only wakes it again once
+    /// [`device`](Registry::device) has been called.
+    pub fn gated(&self) -> Arc<Notify> {
+        self.gated.clone()
+    }
+
+    /// Enables spawn gating unless it is already enabled on the current device.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn enable_spawn_gating(
+        &mut self,
+    ) -> Result<impl Future<Output = Result<()>> + Send + 'static> {
+        // Reconnecting starts over with a new flag, which the future of the old device no
+        // longer sets.
+        self.device()?;
+        let enabled = self.spawn_gating.clone();
+        let enable = if enabled.load(Ordering::Relaxed) {
+            None
+        } else {
+            Some(self.device()?.enable_spawn_gating_async())
+        };
+        Ok(async move {
+            if let Some(enable) = enable {
+                enable.await?;
+                enabled.store(true, Ordering::Relaxed);
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the spawns held by spawn gating.
+    ///
+    /// The returned future does not borrow the registry, so the lock can be released while
+    /// waiting for the list.
+    pub fn pending_spawns(
+        &mut self,
+    ) -> Result<impl Future<Output = Result<Vec<Spawn>>> + Send + 'static> {
+        Ok(self.device()?.enumerate_pending_spawn_async())
+    }
+
+    /// Resumes a suspended process without loading anything into it.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn resume(
+        &mut self,
+        pid: u32,
+    ) -> Result<impl Future<Output = Result<()>> + Send + 'static> {
+        Ok(self.device()?.resume_async(pid))
+    }
+
+    /// Loads `source` into the gated process `pid` and resumes it.
+    ///
+    /// The process is resumed untouched if the script cannot be loaded, so a broken rule never
+    /// keeps an application from starting.
+    pub fn release(
+        &mut self,
+        pid: u32,
+        package: String,
+        source: &str,
+        name: Option<&str>,
+    ) -> Result<impl Future<Output = Result<Injected>> + Send + 'static> {
+        let loader = self.loader()?;
+        let id = self.next_id();
+        let spec = Spec::new(
+            pid,
+            Realm::Native,
+            package,
+            source.to_string(),
+            script_name(id, name),
+        );
+        Ok(async move {
+            let injected = loader.clone().inject(id, spec).await;
+            let resume = loader.device.resume_async(pid);
+            if let Err(e) = resume.await {
+                if let Ok(injected) = injected {
+                    let _ = injected.entry.unload().await;
+                }
+                return Err(e);
+            }
+            injected
+        })
+    }
+
+    /// Returns the children held by child gating.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn pending_children(
+        &mut self,
+    ) -> Result<impl Future<Output = Result<Vec<Child>>> + Send + 'static> {
+        Ok(self.device()?.enumerate_pending_children_async())
+    }
+
+    /// Returns whether any script is loaded into `pid`.
+    pub fn has_scripts(&self, pid: u32) -> bool {
+        self.scripts.values().any(|entry| entry.pid == pid)
+    }
+
+    /// Loads the scripts of `child`'s parent into it and resumes it.
+    ///
+    /// A child whose parent has no scripts left is resumed untouched, as is a child any script
+    /// cannot be loaded into, without the scripts already loaded.
+    pub fn adopt(
+        &mut self,
+        child: &Child,
+    ) -> Result<impl Future<Output = Result<Vec<Injected>>> + Send + 'static> {
+        let loader = self.loader()?;
+        let package = child
+            .identifier
+            .clone()
+            .or_else(|| child.path.clone())
+            .unwrap_or_else(|| child.pid.to_string());
+        let parent_scripts: Vec<(Realm, String, String)> = self
+            .scripts
+            .values()
+            .filter(|entry| entry.pid == child.parent_pid)
+            .map(|entry| {
+                (
+                    entry.realm,
+                    entry.source.clone(),
+                    format!("{}@{}", entry.name, child.pid),
+                )
+            })
+            .collect();
+        let specs: Vec<(ScriptId, Spec)> = parent_scripts
+            .into_iter()
+            .map(|(realm, source, name)| {
+                let spec = Spec::new(child.pid, realm, package.clone(), source, name);
+                (self.next_id(), spec)
+            })
+            .collect();
+
+        let pid = child.pid;
+        Ok(async move {
+            let mut loaded = Vec::new();
+            for (id, spec) in specs {
+                match loader.clone().inject(id, spec).await {
+                    Ok(injected) => loaded.push(injected),
+                    Err(e) => {
+                        for injected in loaded {
+                            let _ = injected.entry.unload().await;
+                        }
+                        let resume = loader.device.resume_async(pid);
+                        let _ = resume.await;
+                        return Err(e);
+                    }
+                }
+            }
+
+            let resume = loader.device.resume_async(pid);
+            resume.await?;
+            Ok(loaded)
+        })
+    }
+
+    /// Keeps a script loaded by a future of the registry, under the id it was loaded as.
+    ///
+    /// Ids of scripts that failed to load are not reused.
+    pub fn insert(&mut self, injected: Injected) -> ScriptId {
+        self.scripts.insert(injected.id, injected.entry);
+        self.watched.notify_one();
+        injected.id
+    }
+
+    /// Returns the notifier woken whenever a script is loaded.
+    pub fn watched(&self) -> Arc<Notify> {
+        self.watched.clone()
+    }
+
+    /// Returns whether any script may need [`resume_interrupted`] or [`reattach`]: its session
+    /// persists through dropped connections or it targets a package.
+    ///
+    /// [`resume_interrupted`]: Registry::resume_interrupted
+    /// [`reattach`]: Registry::reattach
+    pub fn watches(&self) -> bool {
+        self.scripts
+            .values()
+            .any(|entry| entry.by_package || !entry.session.persist_timeout().is_zero())
+    }
+
+    /// Starts resuming the sessions whose connection dropped, keeping their scripts and hooks.
+    ///
+    /// Returns the ids of the scripts concerned, each with a future that does not borrow the
+    /// registry. Sessions past their persist timeout are detached for good and left to
+    /// [`reattach`](Registry::reattach).
+    pub fn resume_interrupted(
+        &self,
+    ) -> Vec<(ScriptId, impl Future<Output = Result<()>> + Send + 'static)> {
+        self.scripts
+            .iter()
+            .filter(|(_, entry)| entry.session.is_detached() && entry.detached().is_none())
+            .map(|(id, entry)| (*id, entry.session.resume_async()))
+            .collect()
+    }
+
+    /// Returns the dead scripts targeting a package, with their package, to be loaded into the
+    /// package's next process with [`reattach`](Registry::reattach).
+    ///
+    /// Scripts unloaded on purpose are left alone.
+    pub fn dead(&self) -> Vec<(ScriptId, String)> {
+        self.scripts
+            .iter()
+            .filter(|(_, entry)| is_dead(entry))
+            .map(|(id, entry)| (*id, entry.package.clone()))
+            .collect()
+    }
+
+    /// Attaches to `pid` and loads the source of dead script `id` again, keeping its id and
+    /// message buffer. Hand the result to [`reattached`](Registry::reattached).
+    ///
+    /// Returns `None` if the script was unloaded or brought back in the meantime.
+    pub fn reattach(
+        &mut self,
+        id: ScriptId,
+        pid: u32,
+    ) -> Result<Option<impl Future<Output = Result<Injected>> + Send + 'static>> {
+        if !self.scripts.get(&id).is_some_and(is_dead) {
+            return Ok(None);
+        }
+        let loader = self.loader()?;
+        let entry = &self.scripts[&id];
+        let spec = Spec {
+            pid,
+            source: entry.source.clone(),
+            ..Spec::of(entry)
+        };
+        Ok(Some(loader.inject(id, spec)))
+    }
+
+    /// Puts a script from [`reattach`](Registry::reattach) in place of its dead entry.
+    ///
+    /// The returned future does not borrow the registry and resolves to `false` if the entry was
+    /// unloaded or brought back meanwhile, after unloading the script again.
+    pub fn reattached(
+        &mut self,
+        injected: Injected,
+    ) -> impl Future<Output = bool> + Send + 'static {
+        self.put(injected, is_dead)
+    }
+
+    /// Loads a new source into a script, resuming or re-attaching its session if it is gone.
+    /// Hand the result to [`reloaded`](Registry::reloaded).
+    ///
+    /// The old script keeps its hooks until the new one is in place, a broken source leaves the
+    /// entry untouched.
+    pub fn reload(
+        &mut self,
+        id: ScriptId,
+        source: &str,
+    ) -> Result<impl Future<Output = Result<Injected>> + Send + 'static> {
+        let entry = self.scripts.get(&id).ok_or(Error::ScriptNotFound { id })?;
+        let loader = if entry.session.is_detached() {
+            Some(self.loader()?)
+        } else {
+            None
+        };
+        let entry = &self.scripts[&id];
+        let spec = Spec {
+            source: source.to_string(),
+            ..Spec::of(entry)
+        };
+        let mut session = entry.session.clone();
+        let detached = entry.detached.clone();
+        let reattach = loader.map(|loader| (session.resume_async(), loader));
+        Ok(async move {
+            if let Some((resume, loader)) = reattach {
+                if resume.await.is_err() {
+                    let (session, detached) = loader.attach(spec.pid, spec.realm).await?;
+                    return load_script(id, spec, session, detached).await;
+                }
+            }
+            session.handle_detached(detached.clone())?;
+            load_script(id, spec, session, detached).await
+        })
+    }
+
+    /// Puts a script from [`reload`](Registry::reload) in place of its entry and unloads the old
+    /// script.
+    ///
+    /// The returned future does not borrow the registry and resolves to `false` if the entry was
+    /// unloaded meanwhile, after unloading the new script again.
+    pub fn reloaded(&mut self, injected: Injected) -> impl Future<Output = bool> + Send + 'static {
+        self.put(injected, |_| true)
+    }
+
+    /// Forgets about a script, then unloads it and detaches its session.
+    ///
+    /// The returned future does not borrow the registry.
+    pub fn unload(
+        &mut self,
+        id: ScriptId,
+    ) -> Result<impl Future<Output = Result<()>> + Send + 'static> {
+        let entry = self
+            .scripts
+            .remove(&id)
+            .ok_or(Error::ScriptNotFound { id })?;
+        Ok(entry.unload())
+    }
+
+    /// Delivers a JSON message, with optional binary data, to the script's `recv()` handlers.
+    pub fn post(&self, id: ScriptId, message: &Value, data: Option<&[u8]>) -> Result<()> {
+        let entry = self.scripts.get(&id).ok_or(Error::ScriptNotFound { id })?;
+        entry.script.post(message.to_string(), data)
+    }
+
+    /// Calls `function` from the script's `rpc.exports` with a JSON array of arguments.
+    ///
+    /// The returned future does not borrow the registry, so the lock can be released while
+    /// waiting for the reply.
+    pub fn call(
+        &self,
+        id: ScriptId,
+        function: &str,
+        args: Value,
+        timeout: Duration,
+    ) -> Result<impl Future<Output = Result<Option<Value>>> + Send + 'static> {
+        let entry = self.scripts.get(&id).ok_or(Error::ScriptNotFound { id })?;
+        Ok(entry
+            .script
+            .exports
+            .call_with_timeout(function, Some(args), timeout))
+    }
+
+    /// Lists the functions the script exposes through `rpc.exports`.
+    pub fn list_exports(
+        &self,
+        id: ScriptId,
+    ) -> Result<impl Future<Output = Result<Vec<String>>> + Send + 'static> {
+        let entry = self.scripts.get(&id).ok_or(Error::ScriptNotFound { id })?;
+        Ok(entry.script.list_exports())
+    }
+
+    pub fn get(&self, id: ScriptId) -> Option<&LoadedScript> {
+        self.scripts.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ScriptId, &LoadedScript)> {
+        self.scripts.iter().map(|(id, entry)| (*id, entry))
+    }
+
+    fn next_id(&mut self) -> ScriptId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn loader(&mut self) -> Result<Loader> {
+        Ok(Loader {
+            device: self.device()?.clone(),
+            persist_timeout: self.persist_timeout,
+            follow_children: self.follow_children,
+        })
+    }
+
+    /// Puts `injected` in place of its entry if `replaces` it, returning a future that unloads
+    /// the old script, or `injected` again if it was not put.
+    fn put(
+        &mut self,
+        injected: Injected,
+        replaces: impl FnOnce(&LoadedScript) -> bool,
+    ) -> impl Future<Output = bool> + Send + 'static {
+        let put = self.scripts.get(&injected.id).is_some_and(replaces);
+        let stale = if put {
+            self.scripts.insert(injected.id, injected.entry).unwrap()
+        } else {
+            injected.entry
+        };
+        async move {
+            if put {
+                // Only the script, a reloaded one shares the session.
+                let unload = stale.script.unload_async();
+                let _ = unload.await;
+            } else {
+                let _ = stale.unload().await;
+            }
+            put
+        }
+    }
+}
+
+/// A script loaded by a future of the registry, kept once handed to [`Registry::insert`] or put
+/// in place of its entry.
+pub struct Injected {
+    pub id: ScriptId,
+    pub entry: LoadedScript,
+}
+
+/// The device and attach policy of the registry, moved into the futures it returns so they do
+/// not borrow it.
+#[derive(Clone)]
+struct Loader {
+    device: Device<'static>,
+    persist_timeout: Duration,
+    follow_children: bool,
+}
+
+impl Loader {
+    /// Attaches to `pid` in `realm` with the registry's persist timeout and child gating
+    /// policy, recording the session's detach into the returned state.
+    async fn attach(self, pid: u32, realm: Realm) -> Result<(Session<'static>, DetachedState)> {
+        let options = SessionOptions::new()
+            .persist_timeout(self.persist_timeout)
+            .realm(realm);
+        let attach = self.device.attach_with_async(pid, &options);
+        drop(options);
+        let mut session = attach.await?;
+        let detached = DetachedState::default();
+        session.handle_detached(detached.clone())?;
+        if session.is_detached() {
+            return Err(Error::SessionDetached);
+        }
+        if self.follow_children {
+            session.enable_child_gating_async().await?;
+        }
+        Ok((session, detached))
+    }
+
+    /// Attaches to the target of `spec` and loads its source as script `id`.
+    async fn inject(self, id: ScriptId, spec: Spec) -> Result<Injected> {
+        let (session, detached) = self.attach(spec.pid, spec.realm).await?;
+        load_script(id, spec, session, detached).await
+    }
+
+    /// Loads the source of `spec` into its suspended target and resumes it.
+    ///
+    /// The process is killed if the script cannot be loaded or the process cannot be resumed.
+    async fn instrument(self, id: ScriptId, spec: Spec) -> Result<Injected> {
+        let pid = spec.pid;
+        let injected = match self.clone().inject(id, spec).await {
+            Ok(injected) => injected,
+            Err(e) => {
+                let kill = self.device.kill_async(pid);
+                let _ = kill.await;
+                return Err(e);
+            }
+        };
+
+        let resume = self.device.resume_async(pid);
+        if let Err(e) = resume.await {
+            drop(injected);
+            let kill = self.device.kill_async(pid);
+            let _ = kill.await;
+            return Err(e);
+        }
+        Ok(injected)
+    }
+}
+
+/// What a script is loaded from, kept in its entry.
+struct Spec {
+    pid: u32,
+    realm: Realm,
+    package: String,
+    name: String,
+    source: String,
+    messages: MessageBuffer,
+    by_package: bool,
+}
+
+impl Spec {
+    fn new(pid: u32, realm: Realm, package: String, source: String, name: String) -> Self {
+        Spec {
+            pid,
+            realm,
+            package,
+            name,
+            source,
+            messages: MessageBuffer::new(MESSAGE_BUFFER_CAPACITY),
+            by_package: false,
+        }
+    }
+
+    /// Follows the package into its next process, see [`Registry::load_package`].
+    fn by_package(mut self) -> Self {
+        self.by_package = true;
+        self
+    }
+
+    /// Loads `entry` again, into the same message buffer.
+    fn of(entry: &LoadedScript) -> Self {
+        Spec {
+            pid: entry.pid,
+            realm: entry.realm,
+            package: entry.package.clone(),
+            name: entry.name.clone(),
+            source: entry.source.clone(),
+            messages: entry.messages.clone(),
+            by_package: entry.by_package,
+        }
+    }
+}
+
+/// Creates and loads the script of `spec` in `session`.
+async fn load_script(
+    id: ScriptId,
+    spec: Spec,
+    session: Session<'static>,
+    detached: DetachedState,
+) -> Result<Injected> {
+    let create = session.create_script_async(
+        &spec.source,
+        &mut ScriptOption::default().set_name(&spec.name),
+    );
+    let script = create.await?;
+    let subscriptions = subscribe(&script, &spec.name, &spec.messages);
+    script.load_async().await?;
+
+    Ok(Injected {
+        id,
+        entry: LoadedScript {
+            pid: spec.pid,
+            package: spec.package,
+            name: spec.name,
+            realm: spec.realm,
+            loaded_at: SystemTime::now(),
+            source: spec.source,
+            messages: spec.messages,
+            by_package: spec.by_package,
+            detached,
+            _subscriptions: subscriptions,
+            script,
+            session,
+        },
+    })
+}
+
+/// Names a script `fggb-{id}` unless the caller named it.
+fn script_name(id: ScriptId, name: Option<&str>) -> String {
+    name.map_or_else(|| format!("fggb-{}", id), String::from)
+}
+
+/// Whether the session of a package target is gone for good, other than by an unload.
+fn is_dead(entry: &LoadedScript) -> bool {
+    entry.by_package
+        && entry
+            .detached()
+            .is_some_and(|detached| detached.reason != DetachReason::ApplicationRequested)
+}
+
+/// Hooks up the observers every loaded script gets: the HTTP message buffer and the console.
+fn subscribe(script: &Script, name: &str, messages: &MessageBuffer) -> Vec<Subscription> {
+    vec![
+        script.subscribe(MessageFilter::All, messages.clone()),
+        script.subscribe(MessageFilter::Logs, ConsoleLog(name.to_string())),
+        script.subscribe(MessageFilter::Errors, ConsoleLog(name.to_string())),
+    ]
+}
+
+/// Prints script logs and errors to the daemon's stdout.
+struct ConsoleLog(String);
+
+impl ScriptHandler for ConsoleLog {
+    fn on_message(&mut self, message: &Message) {
+        match message {
+            Message::Log(log) => println!("[{}] {}", self.0, log.payload),
+            Message::Error(error) => println!(
+                "[{}] {} ({}:{})",
+                self.0, error.description, error.file_name, error.line_number
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Records the detach of a script's session, shared with the session's signal handler.
+#[derive(Clone, Default)]
+struct DetachedState(Arc<Mutex<Option<Detached>>>);
+
+impl DetachedState {
+    fn get(&self) -> Option<Detached> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl SessionHandler for DetachedState {
+    fn on_detached(&mut self, reason: DetachReason, crash: Option<&CrashReport>) {
+        *self.0.lock().unwrap() = Some(Detached {
+            reason,
+            crash: crash.cloned(),
+            at: SystemTime::now(),
+        });
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
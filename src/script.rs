@@ -4,24 +4,33 @@
  * Licence: wxWindows Library Licence, Version 3.1
  */
 use crate::bind;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::{cell::RefCell, marker::PhantomData, rc::Rc};
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 use std::{
     ffi::{c_char, c_void, CStr, CString},
     ptr::null_mut,
 };
+use tokio::sync::oneshot;
 
-use crate::{Error, Result};
+use crate::error::take_error;
+use crate::{connect_signal, disconnect_signals, Error, Result, SendPtr, FRIDA};
 
 /// Represents a Frida message
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(tag = "type")]
 #[serde(rename_all = "lowercase")]
 pub enum Message {
     /// Message of type "send"
     Send(MessageSend),
+    /// Reply to a `frida:rpc` request, sent by the script as a "send" message.
+    #[serde(skip_deserializing)]
+    Rpc(RpcReply),
     /// Message of type "log"
     Log(MessageLog),
     /// Message of type "error"
@@ -31,14 +40,17 @@ pub enum Message {
 }
 
 /// Send Message.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct MessageSend {
-    /// Payload of a Send Message.
-    pub payload: SendPayload,
+    /// Payload passed to `send()`, any JSON value.
+    pub payload: Value,
+    /// Binary data passed as the second argument of `send()`, if any.
+    #[serde(skip)]
+    pub data: Option<Vec<u8>>,
 }
 
 /// Log Message.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct MessageLog {
     /// Log Level.
     pub level: MessageLogLevel,
@@ -48,7 +60,7 @@ pub struct MessageLog {
 
 /// Error message.
 /// This message is sent when a JavaScript runtime error occurs, such as a misspelled word.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageError {
     /// Error description.
@@ -65,7 +77,7 @@ pub struct MessageError {
 
 /// Represents a Message Log Level Types.
 /// Used by `MessageLog._level`
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum MessageLogLevel {
     /// Indicates an informal message.
@@ -78,53 +90,93 @@ pub enum MessageLogLevel {
     Error,
 }
 
-/// Represents a MessageSend's payload.
-#[derive(Deserialize, Debug)]
-pub struct SendPayload {
-    /// Send message type
-    pub r#type: String,
-    /// Send message ID
+/// Represents a reply to a `frida:rpc` request.
+#[derive(Serialize, Debug)]
+pub struct RpcReply {
+    /// ID of the request being answered.
     pub id: usize,
-    /// Send message result.
+    /// Either "ok" or "error".
     pub result: String,
-    /// Send message returns.
+    /// Returned value, or the error message when `result` is "error".
     pub returns: Value,
 }
 
-unsafe extern "C" fn call_on_message<I: ScriptHandler>(
+impl RpcReply {
+    /// Extracts a reply from a `["frida:rpc", id, result, returns, ...]` payload. Error
+    /// replies carry the error name and stack after the message, which are ignored.
+    fn from_payload(payload: &Value) -> Option<Self> {
+        let payload = payload.as_array()?;
+        if payload.first()? != "frida:rpc" {
+            return None;
+        }
+        Some(RpcReply {
+            id: payload.get(1)?.as_u64()? as usize,
+            result: payload.get(2)?.as_str()?.into(),
+            returns: payload.get(3).cloned().unwrap_or(Value::Null),
+        })
+    }
+}
+
+unsafe extern "C" fn call_on_message(
     _script_ptr: *mut bind::_FridaScript,
     message: *const i8,
-    _data: &bind::_GBytes,
+    data: *mut bind::_GBytes,
     user_data: *mut c_void,
 ) {
     let c_msg = CStr::from_ptr(message as *const c_char)
         .to_str()
         .unwrap_or_default();
+    let data = if data.is_null() {
+        None
+    } else {
+        let mut size = 0;
+        let ptr = bind::_frida_g_bytes_get_data(data, &mut size);
+        Some(std::slice::from_raw_parts(ptr as *const u8, size as usize).to_vec())
+    };
+    let callback_handler = &*(user_data as *const CallbackHandler);
+
+    let formatted_msg = match parse_message(c_msg, data) {
+        Message::Rpc(reply) => match callback_handler.resolve(reply) {
+            Some(unmatched) => Message::Rpc(unmatched),
+            None => return,
+        },
+        other => other,
+    };
+
+    callback_handler.dispatch(&formatted_msg);
+}
 
-    let formatted_msg: Message = serde_json::from_str(c_msg).unwrap_or_else(|err| {
+unsafe extern "C" fn call_on_destroyed(
+    _script_ptr: *mut bind::_FridaScript,
+    user_data: *mut c_void,
+) {
+    let callback_handler = &*(user_data as *const CallbackHandler);
+    callback_handler.fail_pending();
+}
+
+fn parse_message(raw: &str, data: Option<Vec<u8>>) -> Message {
+    let other = |err: serde_json::Error| {
         Message::Other(serde_json::json!({
             "error": err.to_string(),
-            "data": c_msg
+            "data": raw
         }))
-    });
-
-    match formatted_msg {
-        Message::Send(msg) => {
-            if msg.payload.r#type == "frida:rpc" {
-                let callback_handler: *mut CallbackHandler = user_data as _;
-                on_message(callback_handler.as_mut().unwrap(), Message::Send(msg));
-            }
-        }
-        _ => {
-            let handler: &mut I = &mut *(user_data as *mut I);
-            handler.on_message(&formatted_msg);
+    };
+
+    let value: Value = match serde_json::from_str(raw) {
+        Ok(value) => value,
+        Err(err) => return other(err),
+    };
+    if value["type"] == "send" {
+        if let Some(reply) = RpcReply::from_payload(&value["payload"]) {
+            return Message::Rpc(reply);
         }
     }
-}
 
-fn on_message(cb_handler: &mut CallbackHandler, message: Message) {
-    let (tx, _) = &cb_handler.channel;
-    let _ = tx.send(message);
+    match serde_json::from_value(value) {
+        Ok(Message::Send(send)) => Message::Send(MessageSend { data, ..send }),
+        Ok(message) => message,
+        Err(err) => other(err),
+    }
 }
 
 /// Represents a script signal handler.
@@ -133,11 +185,62 @@ pub trait ScriptHandler {
     fn on_message(&mut self, message: &Message);
 }
 
+/// Selects which messages a subscribed [`ScriptHandler`] receives.
+#[derive(Debug, Clone)]
+pub enum MessageFilter {
+    /// Every message.
+    All,
+    /// [`Message::Log`] only.
+    Logs,
+    /// [`Message::Error`] only.
+    Errors,
+    /// [`Message::Send`] only.
+    Sends,
+    /// [`Message::Send`] whose payload is an object with the given `type` field.
+    SendType(String),
+}
+
+impl MessageFilter {
+    /// Returns whether `message` passes the filter.
+    pub fn matches(&self, message: &Message) -> bool {
+        match (self, message) {
+            (MessageFilter::All, _) => true,
+            (MessageFilter::Logs, Message::Log(_)) => true,
+            (MessageFilter::Errors, Message::Error(_)) => true,
+            (MessageFilter::Sends, Message::Send(_)) => true,
+            (MessageFilter::SendType(r#type), Message::Send(send)) => {
+                send.payload["type"].as_str() == Some(r#type)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Keeps a handler subscribed to a script's messages, dropping it unsubscribes the handler.
+///
+/// A subscription may outlive its [`Script`], in which case dropping it is a no-op.
+#[must_use = "dropping a Subscription unsubscribes its handler"]
+pub struct Subscription {
+    id: u64,
+    callback_handler: Weak<CallbackHandler>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(callback_handler) = self.callback_handler.upgrade() {
+            callback_handler.remove_handler(self.id);
+        }
+    }
+}
+
+/// Time to wait for an RPC reply before failing with [`Error::RpcTimeout`].
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Represents a Frida script.
 pub struct Script<'a> {
     script_ptr: *mut bind::_FridaScript,
-    rpc_id_counter: Rc<RefCell<usize>>,
-    callback_handler: Rc<RefCell<CallbackHandler>>,
+    callback_handler: Arc<CallbackHandler>,
+    signal_handler_ids: Vec<bind::gulong>,
     ///Exports of the script.
     pub exports: Exports<'a>,
     phantom: PhantomData<&'a bind::_FridaScript>,
@@ -146,32 +249,40 @@ pub struct Script<'a> {
 /// This represents the exports of the script.
 pub struct Exports<'a> {
     script_ptr: *mut bind::_FridaScript,
-    rpc_id_counter: Rc<RefCell<usize>>,
-    callback_handler: Rc<RefCell<CallbackHandler>>,
+    callback_handler: Arc<CallbackHandler>,
     phantom: PhantomData<&'a bind::_FridaScript>,
 }
 
-impl<'a> Exports<'a> {
-    fn inc_id(&mut self) -> usize {
-        let mut counter_borrow = self.rpc_id_counter.borrow_mut();
-        *counter_borrow += 1;
-        *counter_borrow
-    }
-}
-
 impl<'a> Script<'a> {
     pub(crate) fn from_raw(script_ptr: *mut bind::_FridaScript) -> Script<'a> {
-        let rpc_counter = Rc::new(RefCell::new(0));
-        let handler = Rc::new(RefCell::new(CallbackHandler::new()));
+        let handler = Arc::new(CallbackHandler::new());
+        let user_data = Arc::as_ptr(&handler) as *mut c_void;
+
+        let signal_handler_ids = unsafe {
+            vec![
+                connect_signal(
+                    script_ptr,
+                    c"message",
+                    call_on_message as *mut c_void,
+                    user_data,
+                ),
+                connect_signal(
+                    script_ptr,
+                    c"destroyed",
+                    call_on_destroyed as *mut c_void,
+                    user_data,
+                ),
+            ]
+        };
+
         Script {
             script_ptr,
             phantom: PhantomData,
-            rpc_id_counter: rpc_counter.clone(),
             callback_handler: handler.clone(),
+            signal_handler_ids,
             exports: Exports {
                 script_ptr,
                 phantom: PhantomData,
-                rpc_id_counter: rpc_counter,
                 callback_handler: handler,
             },
         }
@@ -185,10 +296,32 @@ impl<'a> Script<'a> {
         if error.is_null() {
             Ok(())
         } else {
-            Err(Error::LoadingFailed)
+            let (code, message) = take_error(error);
+            Err(Error::LoadingFailed { code, message })
         }
     }
 
+    /// Loads the script without blocking the calling thread.
+    pub fn load_async(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let script = SendPtr::new(self.script_ptr);
+        FRIDA.run_async(
+            self.script_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_script_load(script.get(), null_mut(), callback, user_data)
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe { bind::frida_script_load_finish(script.get(), result, &mut error) };
+                if error.is_null() {
+                    Ok(())
+                } else {
+                    let (code, message) = take_error(error);
+                    Err(Error::LoadingFailed { code, message })
+                }
+            },
+        )
+    }
+
     /// Unloads the script from the process.
     pub fn unload(&self) -> Result<()> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
@@ -197,12 +330,39 @@ impl<'a> Script<'a> {
         if error.is_null() {
             Ok(())
         } else {
-            Err(Error::UnloadingFailed)
+            let (code, message) = take_error(error);
+            Err(Error::UnloadingFailed { code, message })
         }
     }
 
+    /// Unloads the script without blocking the calling thread.
+    pub fn unload_async(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let script = SendPtr::new(self.script_ptr);
+        FRIDA.run_async(
+            self.script_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_script_unload(script.get(), null_mut(), callback, user_data)
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe { bind::frida_script_unload_finish(script.get(), result, &mut error) };
+                if error.is_null() {
+                    Ok(())
+                } else {
+                    let (code, message) = take_error(error);
+                    Err(Error::UnloadingFailed { code, message })
+                }
+            },
+        )
+    }
+
     /// Handles the `message` signal for the script and wraps into [`ScriptHandler`].
     ///
+    /// The handler receives every message for the lifetime of the script, use
+    /// [`subscribe`](Script::subscribe) to filter messages or remove the handler later.
+    /// RPC replies are consumed by [`Exports`], only replies nobody is waiting for reach the
+    /// handler as [`Message::Rpc`].
+    ///
     /// # Example
     ///
     /// ```
@@ -216,151 +376,123 @@ impl<'a> Script<'a> {
     ///     }
     /// }
     /// ```
-    pub fn handle_message<I: ScriptHandler + 'static>(&mut self, handler: I) -> Result<()> {
-        let message = CString::new("message").map_err(|_| Error::CStringFailed)?;
-        let mut borrowed_callback_handler = self.callback_handler.borrow_mut();
-        (*borrowed_callback_handler).add_handler(handler);
-        let user_data =
-            (&(*borrowed_callback_handler) as *const _ as *mut CallbackHandler) as *mut c_void;
-        unsafe {
-            let callback = Some(std::mem::transmute::<
-                *mut std::ffi::c_void,
-                unsafe extern "C" fn(),
-            >(call_on_message::<I> as *mut c_void));
-
-            bind::_frida_g_signal_connect_data(
-                self.script_ptr as _,
-                message.as_ptr(),
-                callback,
-                user_data,
-                None,
-                0,
-            )
-        };
-
+    pub fn handle_message<I: ScriptHandler + Send + 'static>(&mut self, handler: I) -> Result<()> {
+        self.callback_handler
+            .add_handler(MessageFilter::All, Box::new(handler));
         Ok(())
     }
 
-    /// Post a JSON-encoded message to the script with optional binary data
+    /// Subscribes `handler` to the messages matching `filter`, alongside any other handler.
     ///
-    /// NOTE: `message` must be valid JSON otherwise the script will throw a SyntaxError
-    pub fn post<S: AsRef<str>>(&self, message: S, data: Option<&[u8]>) -> Result<()> {
-        let message = CString::new(message.as_ref()).map_err(|_| Error::CStringFailed)?;
-
-        unsafe {
-            let g_data = if let Some(data) = data {
-                bind::_frida_g_bytes_new(data.as_ptr() as _, data.len() as _)
-            } else {
-                std::ptr::null_mut()
-            };
-            bind::frida_script_post(self.script_ptr as _, message.as_ptr() as _, g_data);
-            bind::_frida_g_bytes_unref(g_data);
+    /// The handler stays registered until the returned [`Subscription`] is dropped. Handlers
+    /// run on the Frida main context and must not drop a subscription of the same script.
+    pub fn subscribe<I: ScriptHandler + Send + 'static>(
+        &self,
+        filter: MessageFilter,
+        handler: I,
+    ) -> Subscription {
+        let id = self.callback_handler.add_handler(filter, Box::new(handler));
+        Subscription {
+            id,
+            callback_handler: Arc::downgrade(&self.callback_handler),
         }
-
-        Ok(())
     }
 
-    fn inc_id(&mut self) -> usize {
-        let mut counter_borrow = self.rpc_id_counter.borrow_mut();
-        *counter_borrow += 1;
-        *counter_borrow
+    /// Post a JSON-encoded message to the script with optional binary data
+    ///
+    /// NOTE: `message` must be valid JSON otherwise the script will throw a SyntaxError
+    pub fn post<S: AsRef<str>>(&self, message: S, data: Option<&[u8]>) -> Result<()> {
+        post_raw(self.script_ptr, message.as_ref(), data)
     }
 
     /// List all the exported attributes from the script's rpc
-    pub fn list_exports(&mut self) -> Result<Vec<String>> {
-        let json_req = {
-            let name = "frida:rpc".into();
-            let id = self.inc_id().into();
-            let rpc_type = "list".into();
-            let rpc_function = Value::Null;
-            let args = Value::Null;
-
-            let rpc_query: [Value; 5] = [name, id, rpc_type, rpc_function, args];
-
-            serde_json::to_string(&rpc_query).unwrap()
-        };
-
-        self.post(&json_req, None).unwrap();
-        let borrowed_callback_handler = self.callback_handler.borrow();
-        let (_, rx) = &borrowed_callback_handler.channel;
-        let rpc_result = rx.recv().unwrap();
-
-        let func_list: Vec<String> = match rpc_result {
-            Message::Send(r) => {
-                let tmp_list: Vec<String> = r
-                    .payload
-                    .returns
-                    .as_array()
-                    .unwrap_or(&Vec::new())
-                    .iter()
-                    .map(|i| i.as_str().unwrap_or("").to_string())
-                    .collect();
-
-                tmp_list
-            }
-            _ => Vec::new(),
-        };
-
-        Ok(func_list)
+    ///
+    /// The returned future does not borrow the script and fails with [`Error::RpcTimeout`]
+    /// after [`DEFAULT_RPC_TIMEOUT`].
+    pub fn list_exports(&self) -> impl Future<Output = Result<Vec<String>>> + Send + 'static {
+        let call = self
+            .callback_handler
+            .request(self.script_ptr, "list", Value::Null, Value::Null);
+
+        async move {
+            let returns = call?.wait(DEFAULT_RPC_TIMEOUT).await?;
+            let func_list = returns
+                .as_ref()
+                .and_then(Value::as_array)
+                .map(|list| {
+                    list.iter()
+                        .map(|i| i.as_str().unwrap_or("").to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(func_list)
+        }
     }
 }
 
 impl<'a> Exports<'a> {
     /// Run exported functions from a Frida script.
-    pub fn call(&mut self, function_name: &str, args: Option<Value>) -> Result<Option<Value>> {
-        let json_req: String = {
-            let name = "frida:rpc";
-            let id = self.inc_id();
-            let rpc_type = "call";
-
-            let args: String = match args {
-                Some(a) => serde_json::to_string(&a).unwrap(),
-                None => "[]".into(),
-            };
-
-            format!(
-                "[\"{}\", {}, \"{}\", \"{}\", {}]",
-                name, id, rpc_type, function_name, args
-            )
-        };
-
-        let message = CString::new(json_req.as_str()).map_err(|_| Error::CStringFailed)?;
-
-        unsafe {
-            let g_data = std::ptr::null_mut();
-            bind::frida_script_post(self.script_ptr as _, message.as_ptr() as _, g_data);
-            bind::_frida_g_bytes_unref(g_data);
-        }
-
-        let borrowed_callback_handler = self.callback_handler.borrow();
-        let (_, rx) = &borrowed_callback_handler.channel;
-        let rpc_result = rx.recv().unwrap();
-
-        match rpc_result {
-            Message::Send(r) => {
-                if r.payload.result == "ok" {
-                    let returns = r.payload.returns;
+    ///
+    /// Waits at most [`DEFAULT_RPC_TIMEOUT`] for the reply, see
+    /// [`call_with_timeout`](Exports::call_with_timeout).
+    pub fn call(
+        &self,
+        function_name: &str,
+        args: Option<Value>,
+    ) -> impl Future<Output = Result<Option<Value>>> + Send + 'static {
+        self.call_with_timeout(function_name, args, DEFAULT_RPC_TIMEOUT)
+    }
 
-                    match returns {
-                        Value::Null => Ok(None),
-                        _ => Ok(Some(returns)),
-                    }
-                } else {
-                    let err_msg = r.payload.returns.to_string();
-                    Err(Error::RpcJsError { message: err_msg })
-                }
-            }
-            _ => Err(Error::RpcUnexpectedMessage),
-        }
+    /// Run exported functions from a Frida script, failing with [`Error::RpcTimeout`] if the
+    /// script does not reply within `timeout`.
+    ///
+    /// Calls may overlap: replies are matched to their call by request id. The returned future
+    /// does not borrow the script.
+    pub fn call_with_timeout(
+        &self,
+        function_name: &str,
+        args: Option<Value>,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Option<Value>>> + Send + 'static {
+        let args = args.unwrap_or_else(|| Value::Array(Vec::new()));
+        let call =
+            self.callback_handler
+                .request(self.script_ptr, "call", function_name.into(), args);
+
+        async move { call?.wait(timeout).await }
     }
 }
 
 impl<'a> Drop for Script<'a> {
     fn drop(&mut self) {
+        disconnect_signals(
+            self.script_ptr,
+            std::mem::take(&mut self.signal_handler_ids),
+            self.callback_handler.clone(),
+        );
+        self.callback_handler.fail_pending();
         unsafe { bind::frida_unref(self.script_ptr as _) }
     }
 }
 
+fn post_raw(script_ptr: *mut bind::_FridaScript, message: &str, data: Option<&[u8]>) -> Result<()> {
+    let message = CString::new(message).map_err(|_| Error::CStringFailed)?;
+
+    unsafe {
+        let g_data = if let Some(data) = data {
+            bind::_frida_g_bytes_new(data.as_ptr() as _, data.len() as _)
+        } else {
+            std::ptr::null_mut()
+        };
+        bind::frida_script_post(script_ptr as _, message.as_ptr() as _, g_data);
+        if !g_data.is_null() {
+            bind::_frida_g_bytes_unref(g_data);
+        }
+    }
+
+    Ok(())
+}
+
 /// The JavaScript runtime of Frida.
 pub enum ScriptRuntime {
     /// Default Frida runtime.
@@ -401,6 +533,7 @@ impl ScriptOption {
 
     /// Set the name of the script.
     pub fn set_name(self, name: &str) -> Self {
+        let name = CString::new(name).unwrap_or_default();
         unsafe { bind::frida_script_options_set_name(self.ptr, name.as_ptr() as _) };
         self
     }
@@ -430,20 +563,427 @@ impl Drop for ScriptOption {
     }
 }
 
+type RpcResult = Result<Option<Value>>;
+type PendingCalls = Arc<Mutex<HashMap<usize, oneshot::Sender<RpcResult>>>>;
+
+/// State shared between a [`Script`], its [`Exports`] and the GSignal callbacks, which run on
+/// the Frida main context.
 struct CallbackHandler {
-    channel: (Sender<Message>, Receiver<Message>),
-    script_handler: Option<Box<dyn ScriptHandler>>,
+    rpc_id_counter: AtomicUsize,
+    pending: PendingCalls,
+    handler_id_counter: AtomicU64,
+    script_handlers: Mutex<Vec<RegisteredHandler>>,
+}
+
+struct RegisteredHandler {
+    id: u64,
+    filter: MessageFilter,
+    handler: Box<dyn ScriptHandler + Send>,
 }
 
 impl CallbackHandler {
     fn new() -> Self {
         Self {
-            channel: channel(),
-            script_handler: None,
+            rpc_id_counter: AtomicUsize::new(0),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            handler_id_counter: AtomicU64::new(0),
+            script_handlers: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn add_handler(&self, filter: MessageFilter, handler: Box<dyn ScriptHandler + Send>) -> u64 {
+        let id = self.handler_id_counter.fetch_add(1, Ordering::Relaxed);
+        self.script_handlers
+            .lock()
+            .unwrap()
+            .push(RegisteredHandler {
+                id,
+                filter,
+                handler,
+            });
+        id
+    }
+
+    fn remove_handler(&self, id: u64) {
+        self.script_handlers
+            .lock()
+            .unwrap()
+            .retain(|registered| registered.id != id);
+    }
+
+    /// Hands a message to every handler whose filter matches it.
+    fn dispatch(&self, message: &Message) {
+        for registered in self.script_handlers.lock().unwrap().iter_mut() {
+            if registered.filter.matches(message) {
+                registered.handler.on_message(message);
+            }
+        }
+    }
+
+    /// Registers a waiter and posts a `frida:rpc` request to the script.
+    fn request(
+        &self,
+        script_ptr: *mut bind::_FridaScript,
+        rpc_type: &str,
+        function: Value,
+        args: Value,
+    ) -> Result<PendingCall> {
+        let id = self.rpc_id_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let json_req = serde_json::json!(["frida:rpc", id, rpc_type, function, args]).to_string();
+        if let Err(e) = post_raw(script_ptr, &json_req, None) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        Ok(PendingCall {
+            id,
+            rx,
+            pending: self.pending.clone(),
+        })
+    }
+
+    /// Hands a reply to the call waiting on its id, giving it back if there is none.
+    fn resolve(&self, reply: RpcReply) -> Option<RpcReply> {
+        let Some(tx) = self.pending.lock().unwrap().remove(&reply.id) else {
+            return Some(reply);
+        };
+        let result = if reply.result == "ok" {
+            match reply.returns {
+                Value::Null => Ok(None),
+                returns => Ok(Some(returns)),
+            }
+        } else {
+            let message = match reply.returns {
+                Value::String(message) => message,
+                other => other.to_string(),
+            };
+            Err(Error::RpcJsError { message })
+        };
+        let _ = tx.send(result);
+        None
+    }
+
+    /// Fails every outstanding call with [`Error::RpcScriptDestroyed`].
+    fn fail_pending(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+}
+
+/// An RPC request waiting for its reply.
+struct PendingCall {
+    id: usize,
+    rx: oneshot::Receiver<RpcResult>,
+    pending: PendingCalls,
+}
+
+impl PendingCall {
+    async fn wait(self, timeout: Duration) -> RpcResult {
+        let PendingCall { id, rx, pending } = self;
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(Error::RpcScriptDestroyed),
+            Err(_) => {
+                pending.lock().unwrap().remove(&id);
+                Err(Error::RpcTimeout)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Registers a waiter for `id`, as [`CallbackHandler::request`] does before posting.
+    fn waiter(handler: &CallbackHandler, id: usize) -> PendingCall {
+        let (tx, rx) = oneshot::channel();
+        handler.pending.lock().unwrap().insert(id, tx);
+        PendingCall {
+            id,
+            rx,
+            pending: handler.pending.clone(),
+        }
+    }
+
+    fn reply(id: usize, result: &str, returns: Value) -> RpcReply {
+        RpcReply {
+            id,
+            result: result.to_string(),
+            returns,
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_replies_by_id() {
+        let handler = CallbackHandler::new();
+        let first = waiter(&handler, 1);
+        let second = waiter(&handler, 2);
+        let third = waiter(&handler, 3);
+
+        assert!(handler.resolve(reply(2, "ok", json!({ "a": 1 }))).is_none());
+        assert!(handler.resolve(reply(3, "ok", Value::Null)).is_none());
+        assert!(handler.resolve(reply(1, "error", json!("boom"))).is_none());
+
+        let timeout = Duration::from_secs(1);
+        assert!(matches!(
+            first.wait(timeout).await,
+            Err(Error::RpcJsError { message }) if message == "boom"
+        ));
+        assert_eq!(second.wait(timeout).await.unwrap(), Some(json!({ "a": 1 })));
+        assert_eq!(third.wait(timeout).await.unwrap(), None);
+    }
+
+    #[test]
+    fn gives_back_replies_nobody_waits_for() {
+        let handler = CallbackHandler::new();
+        let _waiting = waiter(&handler, 1);
+
+        let unmatched = handler.resolve(reply(7, "ok", json!(true))).unwrap();
+        assert_eq!(unmatched.id, 7);
+        assert!(handler.pending.lock().unwrap().contains_key(&1));
+    }
+
+    #[tokio::test]
+    async fn times_out_and_forgets_the_call() {
+        let handler = CallbackHandler::new();
+        let call = waiter(&handler, 1);
+
+        let result = call.wait(Duration::from_millis(10)).await;
+        assert!(matches!(result, Err(Error::RpcTimeout)));
+        assert!(handler.pending.lock().unwrap().is_empty());
+        // A late reply is handed back like any unmatched one.
+        assert!(handler.resolve(reply(1, "ok", Value::Null)).is_some());
+    }
+
+    #[tokio::test]
+    async fn fails_pending_calls_when_the_script_is_destroyed() {
+        let handler = CallbackHandler::new();
+        let first = waiter(&handler, 1);
+        let second = waiter(&handler, 2);
+
+        handler.fail_pending();
+        let timeout = Duration::from_secs(1);
+        assert!(matches!(
+            first.wait(timeout).await,
+            Err(Error::RpcScriptDestroyed)
+        ));
+        assert!(matches!(
+            second.wait(timeout).await,
+            Err(Error::RpcScriptDestroyed)
+        ));
+    }
+
+    #[test]
+    fn parses_send_messages_with_and_without_data() {
+        let raw = r#"{"type":"send","payload":{"type":"hit","addr":"0x10"}}"#;
+        let Message::Send(send) = parse_message(raw, Some(vec![1, 2, 3])) else {
+            panic!("not a send message");
+        };
+        assert_eq!(send.payload, json!({ "type": "hit", "addr": "0x10" }));
+        assert_eq!(send.data, Some(vec![1, 2, 3]));
+
+        let Message::Send(send) = parse_message(r#"{"type":"send","payload":[1,"a"]}"#, None)
+        else {
+            panic!("not a send message");
+        };
+        assert_eq!(send.payload, json!([1, "a"]));
+        assert_eq!(send.data, None);
+    }
+
+    #[test]
+    fn parses_log_and_error_messages() {
+        let Message::Log(log) = parse_message(
+            r#"{"type":"log","level":"warning","payload":"careful"}"#,
+            None,
+        ) else {
+            panic!("not a log message");
+        };
+        assert!(matches!(log.level, MessageLogLevel::Warning));
+        assert_eq!(log.payload, "careful");
+
+        let raw = json!({
+            "type": "error",
+            "description": "ReferenceError: x is not defined",
+            "stack": "at main",
+            "fileName": "/script.js",
+            "lineNumber": 3,
+            "columnNumber": 7,
+        });
+        let Message::Error(error) = parse_message(&raw.to_string(), None) else {
+            panic!("not an error message");
+        };
+        assert_eq!(error.description, "ReferenceError: x is not defined");
+        assert_eq!(error.file_name, "/script.js");
+        assert_eq!((error.line_number, error.column_number), (3, 7));
+    }
+
+    #[test]
+    fn splits_out_rpc_replies() {
+        let raw = r#"{"type":"send","payload":["frida:rpc",3,"ok",{"a":1}]}"#;
+        let Message::Rpc(reply) = parse_message(raw, None) else {
+            panic!("not an rpc reply");
+        };
+        assert_eq!((reply.id, reply.result.as_str()), (3, "ok"));
+        assert_eq!(reply.returns, json!({ "a": 1 }));
+
+        let raw = r#"{"type":"send","payload":["frida:rpc",4,"error","boom","Error","at f"]}"#;
+        let Message::Rpc(reply) = parse_message(raw, None) else {
+            panic!("not an rpc reply");
+        };
+        assert_eq!((reply.id, reply.result.as_str()), (4, "error"));
+        assert_eq!(reply.returns, json!("boom"));
+
+        let Message::Rpc(reply) =
+            parse_message(r#"{"type":"send","payload":["frida:rpc",5,"ok"]}"#, None)
+        else {
+            panic!("not an rpc reply");
+        };
+        assert_eq!(reply.returns, Value::Null);
+    }
+
+    #[test]
+    fn keeps_malformed_rpc_replies_and_invalid_json_apart() {
+        for payload in [r#"["frida:rpc","x","ok"]"#, r#"["other",1,"ok",null]"#] {
+            let raw = format!(r#"{{"type":"send","payload":{}}}"#, payload);
+            assert!(matches!(parse_message(&raw, None), Message::Send(_)));
+        }
+
+        let Message::Other(other) = parse_message("not json", None) else {
+            panic!("not an other message");
+        };
+        assert_eq!(other["data"], "not json");
+        assert!(other["error"].is_string());
+    }
+
+    /// Records the payloads it receives.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.lock().unwrap())
         }
     }
 
-    fn add_handler<I: ScriptHandler + 'static>(&mut self, handler: I) {
-        self.script_handler = Some(Box::from(handler));
+    impl ScriptHandler for Recorder {
+        fn on_message(&mut self, message: &Message) {
+            let label = match message {
+                Message::Send(send) => send.payload.to_string(),
+                Message::Log(log) => log.payload.clone(),
+                Message::Error(error) => error.description.clone(),
+                _ => "other".to_string(),
+            };
+            self.0.lock().unwrap().push(label);
+        }
+    }
+
+    /// Subscribes like [`Script::subscribe`], without a script.
+    fn subscribe(
+        handler: &Arc<CallbackHandler>,
+        filter: MessageFilter,
+    ) -> (Subscription, Recorder) {
+        let recorder = Recorder::default();
+        let id = handler.add_handler(filter, Box::new(recorder.clone()));
+        let subscription = Subscription {
+            id,
+            callback_handler: Arc::downgrade(handler),
+        };
+        (subscription, recorder)
+    }
+
+    fn send(payload: Value) -> Message {
+        Message::Send(MessageSend {
+            payload,
+            data: None,
+        })
+    }
+
+    fn log(payload: &str) -> Message {
+        Message::Log(MessageLog {
+            level: MessageLogLevel::Info,
+            payload: payload.to_string(),
+        })
+    }
+
+    fn error(description: &str) -> Message {
+        Message::Error(MessageError {
+            description: description.to_string(),
+            stack: String::new(),
+            file_name: String::new(),
+            line_number: 0,
+            column_number: 0,
+        })
+    }
+
+    #[test]
+    fn filters_select_their_messages() {
+        let hit = send(json!({ "type": "hit" }));
+        let miss = send(json!({ "type": "miss" }));
+        let untyped = send(json!("hit"));
+        let messages = [&hit, &miss, &untyped, &log("a"), &error("b")];
+        let selected = |filter: MessageFilter| -> Vec<bool> {
+            messages
+                .iter()
+                .map(|message| filter.matches(message))
+                .collect()
+        };
+
+        assert_eq!(selected(MessageFilter::All), [true; 5]);
+        assert_eq!(
+            selected(MessageFilter::Logs),
+            [false, false, false, true, false]
+        );
+        assert_eq!(
+            selected(MessageFilter::Errors),
+            [false, false, false, false, true]
+        );
+        assert_eq!(
+            selected(MessageFilter::Sends),
+            [true, true, true, false, false]
+        );
+        assert_eq!(
+            selected(MessageFilter::SendType("hit".into())),
+            [true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn dispatches_to_matching_subscribers_only() {
+        let handler = Arc::new(CallbackHandler::new());
+        let (_all, all) = subscribe(&handler, MessageFilter::All);
+        let (_logs, logs) = subscribe(&handler, MessageFilter::Logs);
+        let (_hits, hits) = subscribe(&handler, MessageFilter::SendType("hit".into()));
+
+        handler.dispatch(&send(json!({ "type": "hit" })));
+        handler.dispatch(&log("loaded"));
+        handler.dispatch(&error("oops"));
+
+        assert_eq!(all.take(), [r#"{"type":"hit"}"#, "loaded", "oops"]);
+        assert_eq!(logs.take(), ["loaded"]);
+        assert_eq!(hits.take(), [r#"{"type":"hit"}"#]);
+    }
+
+    #[test]
+    fn dropping_a_subscription_unsubscribes() {
+        let handler = Arc::new(CallbackHandler::new());
+        let (first, first_seen) = subscribe(&handler, MessageFilter::All);
+        let (_second, second_seen) = subscribe(&handler, MessageFilter::All);
+
+        drop(first);
+        handler.dispatch(&log("after"));
+        assert!(first_seen.take().is_empty());
+        assert_eq!(second_seen.take(), ["after"]);
+    }
+
+    #[test]
+    fn subscriptions_may_outlive_their_script() {
+        let handler = Arc::new(CallbackHandler::new());
+        let (subscription, _) = subscribe(&handler, MessageFilter::All);
+        drop(handler);
+        drop(subscription);
     }
 }
@@ -1,149 +1,842 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
 use std::collections::HashMap;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::ffi::CString;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, timeout};
 
-use crate::{enumerate_processes, Channel, MsgType, Pipe};
+use crate::http::{self, Request, RequestError, Response};
+use crate::registry::{Injected, Registry, ScriptId};
+use crate::{
+    configure, AutoInject, Channel, Error, ErrorKind, MsgType, Pipe, ProcessQueryOptions, Realm,
+    Scope, SpawnOptions, Variant, DEFAULT_MAX_BODY_SIZE, DEFAULT_PERSIST_TIMEOUT,
+    DEFAULT_RPC_TIMEOUT,
+};
+
+/// Longest wait for a gated spawn or child in daemon mode, after which the device is checked
+/// again in case it was lost, and delay before retrying after it could not be reached.
+const GATING_RETRY: Duration = Duration::from_secs(1);
+/// How often interrupted sessions are resumed and dead scripts re-attached in daemon mode.
+const RESUME_INTERVAL: Duration = Duration::from_secs(1);
 
 pub async fn run(channel: Channel<Pipe<String>>) -> Result<(), Box<dyn std::error::Error>> {
     let listener = TcpListener::bind("127.0.0.1:6699").await?;
     println!("Socket server running on 127.0.0.1:6699");
+    let conf = configure().ok();
+    let max_body_size = conf
+        .as_ref()
+        .map_or(DEFAULT_MAX_BODY_SIZE, |conf| conf.max_body_size);
+    let follow_children = conf.as_ref().is_some_and(|conf| conf.follow_children);
+    let frontmost_by_default = conf.as_ref().is_some_and(|conf| conf.frontmost_by_default);
+    let persist_timeout = conf
+        .as_ref()
+        .map_or(DEFAULT_PERSIST_TIMEOUT, |conf| conf.persist_timeout);
+    let auto_inject = conf.map(|conf| conf.auto_inject).unwrap_or_default();
+    let registry = Arc::new(Mutex::new(
+        Registry::new()
+            .follow_children(follow_children)
+            .frontmost_by_default(frontmost_by_default)
+            .persist_timeout(Duration::from_secs(persist_timeout.into())),
+    ));
+    if !auto_inject.is_empty() || follow_children {
+        tokio::spawn(watch_gating(auto_inject, registry.clone()));
+    }
+    tokio::spawn(watch_sessions(registry.clone()));
     // todo! restart on port in use
     loop {
         let (socket, _) = listener.accept().await?;
         tokio::spawn({
             let mut ch = channel.clone();
+            let registry = registry.clone();
             let mut stream = tokio::io::BufReader::new(socket);
             async move {
-                let mut buf = vec![0; 1024];
-                let n = match stream.read(&mut buf).await {
-                    Ok(n) if n == 0 => return, // Connection closed
-                    Ok(n) => n,
-                    Err(_) => return,
+                let response = match http::read_request(&mut stream, max_body_size).await {
+                    Ok(request) => handle(request, &mut ch, &registry).await,
+                    Err(e) => match e.status() {
+                        Some(status) => {
+                            let kind = match e {
+                                RequestError::Malformed(_) => ErrorKind::InvalidArgument,
+                                _ => ErrorKind::Other,
+                            };
+                            error_response(status, kind, &e.to_string())
+                        }
+                        None => return,
+                    },
                 };
+                let _ = response.write_to(&mut stream).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+    }
+}
 
-                let request = String::from_utf8_lossy(&buf[..n]);
-
-                if let Some((method, path)) = parse_method_and_path(&request) {
-                    if method == "POST" {
-                        if let Some(body_start) = request.find("\r\n\r\n") {
-                            let body = &request[(body_start + 4)..];
-
-                            if body.len() == 0 {
-                                stream.write_all(format!(
-                                            "HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\n\r\nNo Script Provided!").as_bytes()).await.unwrap();
-                            }
-
-                            // Parse query
-
-                            if let Some(query) = path.split('?').nth(1) {
-                                let params = parse_query_params(query);
-                                if let Some(app_pid) = params.get("pid") {
-                                    let message = String::from(app_pid);
-                                    let _ = ch
-                                        .send(Pipe {
-                                            msg: MsgType::Socket,
-                                            payload: message.clone(),
-                                        })
-                                        .await;
-                                } else {
-                                    stream.write_all(format!(
-                                            "HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\n\r\nNo Pid Provided!").as_bytes()).await.unwrap();
-                                }
-                            }
-
-                            match start_frida_bindings(0, body) {
-                                Ok((status, package)) => {
-                                    stream.write_all(format!(
-                                                "HTTP/1.1 {} ok\r\nContent-Type: text/plain\r\n\r\nScript Loaded in {} Successfully",
-                                                status,package
-                                            ).as_bytes()).await.unwrap();
-                                }
-                                Err(kind) => {
-                                    stream.write_all(format!(
-                                                "HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\n\r\n{}",
-                                                kind
-                                            ).as_bytes()).await.unwrap();
-                                }
-                            }
-                            let _ = stream.shutdown().await;
-                            return;
-                        }
+/// Daemon mode: releases the processes held by spawn gating, when `rules` are configured, and
+/// by child gating, when the registry follows children.
+async fn watch_gating(rules: Vec<AutoInject>, registry: Arc<Mutex<Registry>>) {
+    if !rules.is_empty() {
+        println!("Spawn gating enabled for {} rule(s)", rules.len());
+    }
+    let (gated, follow_children) = {
+        let registry = registry.lock().await;
+        (registry.gated(), registry.follows_children())
+    };
+    loop {
+        let mut result = Ok(());
+        if !rules.is_empty() {
+            result = release_spawns(&rules, &registry).await;
+        }
+        if result.is_ok() && follow_children {
+            result = release_children(&registry).await;
+        }
+
+        match result {
+            Ok(()) => {
+                let _ = timeout(GATING_RETRY, gated.notified()).await;
+            }
+            Err(e) => {
+                eprintln!("Gating error: {}", e);
+                sleep(GATING_RETRY).await;
+            }
+        }
+    }
+}
+
+/// Daemon mode: resumes the sessions interrupted by a frida-server or socket hiccup before their
+/// persist timeout runs out, so their scripts keep running, and loads the dead scripts of
+/// package targets into the package's next process.
+///
+/// Idles until a script that may need either is loaded. The registry is only locked to collect
+/// the work, to start re-attaching a script and to put it in place, not while frida-server
+/// answers.
+async fn watch_sessions(registry: Arc<Mutex<Registry>>) {
+    let watched = registry.lock().await.watched();
+    loop {
+        let (interrupted, dead) = {
+            let registry = registry.lock().await;
+            if !registry.watches() {
+                drop(registry);
+                watched.notified().await;
+                continue;
+            }
+            (registry.resume_interrupted(), registry.dead())
+        };
+
+        let mut resumed = Vec::new();
+        for (id, resume) in interrupted {
+            if resume.await.is_ok() {
+                resumed.push(id);
+            }
+        }
+        let mut reattached = Vec::new();
+        for (id, package) in dead {
+            let Ok(find) = registry.lock().await.package_pid(&package) else {
+                continue;
+            };
+            let Ok(pid) = find.await else {
+                continue;
+            };
+            let reattach = registry.lock().await.reattach(id, pid).transpose();
+            let Some(reattach) = reattach else {
+                continue;
+            };
+            match settle(reattach).await {
+                Ok(injected) => {
+                    let put = registry.lock().await.reattached(injected);
+                    if put.await {
+                        reattached.push(id);
                     }
-                } else {
-                    stream.write_all("ok".as_bytes()).await.unwrap();
                 }
+                Err(e) => eprintln!(
+                    "Re-attaching #{} to {}@pid-{} failed: {}",
+                    id, package, pid, e
+                ),
+            }
+        }
 
-                let message = String::from_utf8_lossy(&buf[..n]).to_string();
-
-                let _ = ch.send(Pipe {
-                    msg: MsgType::POST,
-                    payload: message,
-                });
-
-                if let Some(message) = ch.receive().await {
-                    println!("Received message from GG watchdog: {}", message);
-                    let _ = ch
-                        .send(Pipe {
-                            msg: MsgType::Socket,
-                            payload: (format!("")),
-                        })
-                        .await;
+        if !resumed.is_empty() {
+            println!("Resumed the sessions of {:?}", resumed);
+        }
+        if !reattached.is_empty() {
+            println!("Re-attached {:?}", reattached);
+        }
+        sleep(RESUME_INTERVAL).await;
+    }
+}
+
+/// Loads the script of the first matching rule into each gated spawn and resumes it.
+/// Processes no rule matches are resumed untouched.
+///
+/// The registry is only locked to start loading a script and to keep it, not while frida-server
+/// answers.
+async fn release_spawns(rules: &[AutoInject], registry: &Mutex<Registry>) -> crate::Result<()> {
+    let enable = registry.lock().await.enable_spawn_gating();
+    settle(enable).await?;
+    let pending = registry.lock().await.pending_spawns()?;
+    for spawn in pending.await? {
+        let identifier = spawn.identifier.unwrap_or_default();
+        let Some(rule) = rules.iter().find(|rule| rule.matches(&identifier)) else {
+            resume(spawn.pid, registry).await;
+            continue;
+        };
+        let result = match tokio::fs::read_to_string(&rule.script).await {
+            Ok(source) => {
+                let release = {
+                    let mut registry = registry.lock().await;
+                    registry.release(spawn.pid, identifier.clone(), &source, None)
+                };
+                match settle(release).await {
+                    Ok(injected) => Ok(registry.lock().await.insert(injected)),
+                    Err(e) => Err(e.to_string()),
                 }
             }
-        });
+            Err(e) => {
+                resume(spawn.pid, registry).await;
+                Err(format!("Cannot read {}: {}", rule.script, e))
+            }
+        };
+        match result {
+            Ok(id) => println!(
+                "Injected {} into {}@pid-{} as #{}",
+                rule.script, identifier, spawn.pid, id
+            ),
+            Err(e) => eprintln!(
+                "Auto-inject into {}@pid-{} failed: {}",
+                identifier, spawn.pid, e
+            ),
+        }
     }
+    Ok(())
 }
 
-fn start_frida_bindings(pid: u32, script: &str) -> crate::Result<(i32, String)> {
-    let device_manager = crate::DeviceManager::obtain(&crate::FRIDA);
-    let local_device = device_manager.get_remote_device("localhost")?;
+/// Loads the parent's scripts into each gated child and resumes it.
+///
+/// The registry is only locked to start loading the scripts and to keep them, not while
+/// frida-server answers.
+async fn release_children(registry: &Mutex<Registry>) -> crate::Result<()> {
+    let pending = registry.lock().await.pending_children()?;
+    for child in pending.await? {
+        let adopt = {
+            let mut registry = registry.lock().await;
+            registry
+                .has_scripts(child.parent_pid)
+                .then(|| registry.adopt(&child))
+        };
+        let Some(adopt) = adopt else {
+            resume(child.pid, registry).await;
+            continue;
+        };
+        let adopted = match settle(adopt).await {
+            Ok(loaded) => {
+                let mut registry = registry.lock().await;
+                Ok(loaded
+                    .into_iter()
+                    .map(|injected| registry.insert(injected))
+                    .collect::<Vec<_>>())
+            }
+            Err(e) => Err(e),
+        };
+        match adopted {
+            Ok(ids) if !ids.is_empty() => println!(
+                "Followed pid-{} into {:?} child pid-{} with {:?}",
+                child.parent_pid, child.origin, child.pid, ids
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!(
+                "Following pid-{} into child pid-{} failed: {}",
+                child.parent_pid, child.pid, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Resumes a gated process untouched, holding the registry lock only to reach the device.
+async fn resume(pid: u32, registry: &Mutex<Registry>) {
+    let resume = registry.lock().await.resume(pid);
+    let _ = settle(resume).await;
+}
+
+/// Awaits a future the registry started, once the lock it was started under is released.
+///
+/// Bind the result of the registry call first, so the guard is dropped at the end of that
+/// statement rather than held across the await.
+async fn settle<T>(
+    started: crate::Result<impl Future<Output = crate::Result<T>>>,
+) -> crate::Result<T> {
+    started?.await
+}
 
-    let apps = enumerate_processes(&local_device)?;
+/// Keeps a script loaded without the registry lock, returning its id, pid and package.
+async fn keep(
+    loaded: crate::Result<Injected>,
+    registry: &Mutex<Registry>,
+) -> crate::Result<(ScriptId, u32, String)> {
+    let injected = loaded?;
+    let (pid, package) = (injected.entry.pid, injected.entry.package.clone());
+    Ok((registry.lock().await.insert(injected), pid, package))
+}
 
-    let session = local_device.attach(pid)?;
+async fn handle(
+    request: Request,
+    ch: &mut Channel<Pipe<String>>,
+    registry: &Mutex<Registry>,
+) -> Response {
+    let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
 
-    if session.is_detached() {
-        return Err(crate::error::Error::SessionDetachError);
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", []) => inject(&request, ch, registry).await,
+        ("POST", ["spawn"]) => spawn(&request, ch, registry).await,
+        ("GET", ["device"]) => device_parameters(registry).await,
+        ("GET", ["processes"]) => list_processes(&request, registry).await,
+        ("GET", ["scripts"]) => list_scripts(registry).await,
+        ("DELETE", ["scripts", id]) => match parse_id(id) {
+            Ok(id) => unload_script(id, registry).await,
+            Err(response) => response,
+        },
+        ("GET", ["scripts", id, "messages"]) => match parse_id(id) {
+            Ok(id) => script_messages(id, &request, registry).await,
+            Err(response) => response,
+        },
+        ("GET", ["scripts", id, "exports"]) => match parse_id(id) {
+            Ok(id) => list_exports(id, registry).await,
+            Err(response) => response,
+        },
+        ("POST", ["scripts", id, "rpc", function]) => match parse_id(id) {
+            Ok(id) => call_export(id, function, &request, registry).await,
+            Err(response) => response,
+        },
+        ("POST", ["scripts", id, "post"]) => match parse_id(id) {
+            Ok(id) => post_message(id, &request, registry).await,
+            Err(response) => response,
+        },
+        ("POST", ["scripts", id, "reload"]) => match parse_id(id) {
+            Ok(id) => reload_script(id, &request, registry).await,
+            Err(response) => response,
+        },
+        (method, _) => error_response(
+            404,
+            ErrorKind::NotSupported,
+            &format!("No route for {} {}", method, request.path),
+        ),
     }
+}
 
-    let mut script_option = crate::script::ScriptOption::default();
-    let script = session.create_script(script, &mut script_option)?;
+async fn inject(
+    request: &Request,
+    ch: &mut Channel<Pipe<String>>,
+    registry: &Mutex<Registry>,
+) -> Response {
+    let body = match script_source(request) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    // `emulated` targets the code translated by the native bridge of x86_64 emulators.
+    let realm = match request.query.get("realm").map(String::as_str) {
+        None | Some("native") => Realm::Native,
+        Some("emulated") => Realm::Emulated,
+        Some(realm) => return bad_request(&format!("Invalid realm: {}", realm)),
+    };
+    let name = request.query.get("name").map(String::as_str);
+    let loaded = match (request.query.get("pid"), request.query.get("package")) {
+        // Targets named by package are followed into the package's next process.
+        (None, Some(package)) => {
+            let load = registry
+                .lock()
+                .await
+                .load_package(package, realm, body, name);
+            let loaded = keep(settle(load).await, registry).await;
+            if let Ok((_, pid, _)) = &loaded {
+                let _ = ch
+                    .send(Pipe {
+                        msg: MsgType::Socket,
+                        payload: pid.to_string(),
+                    })
+                    .await;
+            }
+            loaded
+        }
+        (pid, _) => {
+            let pid = match pid {
+                Some(app_pid) => match app_pid.parse::<u32>() {
+                    Ok(pid) => pid,
+                    Err(_) => return bad_request(&format!("Invalid pid: {}", app_pid)),
+                },
+                None => {
+                    let frontmost = {
+                        let mut registry = registry.lock().await;
+                        if !registry.targets_frontmost() {
+                            return bad_request("No Pid Provided!");
+                        }
+                        registry.frontmost_pid()
+                    };
+                    match settle(frontmost).await {
+                        Ok(pid) => pid,
+                        Err(e) => return frida_error(e),
+                    }
+                }
+            };
+            let _ = ch
+                .send(Pipe {
+                    msg: MsgType::Socket,
+                    payload: pid.to_string(),
+                })
+                .await;
+            let load = registry.lock().await.load(pid, realm, body, name);
+            keep(settle(load).await, registry).await
+        }
+    };
 
-    script.load()?;
-    /*
-    todo! generate sig for successful attach instead of fancy response
-    sweaping buffer in lua with frida buffer for interaction irt.
-     */
-    let mut package = String::new();
-    if let Some((_, (_, p))) = apps.iter().enumerate().find(|(_, (num, _))| *num == pid) {
-        package = String::from(p);
+    match loaded {
+        Ok((id, pid, package)) => Response::json(
+            200,
+            &json!({
+                "id": id,
+                "pid": pid,
+                "package": package,
+                "message": format!("Script Loaded in {} Successfully", package),
+            }),
+        ),
+        Err(e) => frida_error(e),
     }
-    Ok((200, package))
 }
 
-fn parse_method_and_path(request: &str) -> Option<(&str, &str)> {
-    let mut parts = request.split_whitespace();
-    let method = parts.next()?;
-    let path = parts.next()?;
-    Some((method, path))
+/// Body of `POST /spawn`.
+#[derive(Deserialize)]
+struct SpawnRequest {
+    /// Package name or path of the program to spawn.
+    #[serde(alias = "package")]
+    program: String,
+    /// Script source to load before the process runs.
+    script: String,
+    /// Full argument vector, including `argv[0]`.
+    argv: Option<Vec<String>>,
+    env: Option<HashMap<String, String>>,
+    cwd: Option<String>,
+    /// Auxiliary spawn options, e.g. `{"uid": 10123}`.
+    aux: Option<HashMap<String, Variant>>,
+    name: Option<String>,
 }
 
-fn parse_query_params(query: &str) -> HashMap<String, String> {
-    query
-        .split('&')
-        .filter_map(|param| {
-            let mut split = param.splitn(2, '=');
-            let key = split.next()?.to_string();
-            let value = split.next()?.to_string();
-            Some((key, value))
+impl SpawnRequest {
+    fn options(&self) -> Option<SpawnOptions<'static>> {
+        let mut options = SpawnOptions::new();
+        if let Some(argv) = &self.argv {
+            if argv.iter().any(|arg| arg.contains('\0')) {
+                return None;
+            }
+            options = options.argv(argv);
+        }
+        if let Some(env) = &self.env {
+            if env
+                .iter()
+                .any(|(k, v)| k.contains('\0') || v.contains('\0'))
+            {
+                return None;
+            }
+            options = options.env(env);
+        }
+        if let Some(cwd) = &self.cwd {
+            options = options.cwd(CString::new(cwd.as_str()).ok()?);
+        }
+        if let Some(aux) = &self.aux {
+            options = options.aux(aux);
+        }
+        Some(options)
+    }
+}
+
+/// Spawns a program suspended, loads the script into it and resumes it, so the script can
+/// hook code that runs during startup.
+async fn spawn(
+    request: &Request,
+    ch: &mut Channel<Pipe<String>>,
+    registry: &Mutex<Registry>,
+) -> Response {
+    let spawn = match serde_json::from_slice::<SpawnRequest>(&request.body) {
+        Ok(spawn) => spawn,
+        Err(e) => return bad_request(&format!("Invalid spawn request: {}", e)),
+    };
+    if spawn.program.is_empty() {
+        return bad_request("No Program Provided!");
+    }
+    if spawn.script.is_empty() {
+        return bad_request("No Script Provided!");
+    }
+    let Some(options) = spawn.options() else {
+        return bad_request("argv, env and cwd must not contain NUL bytes");
+    };
+
+    let load = registry.lock().await.spawn(
+        &spawn.program,
+        options,
+        &spawn.script,
+        spawn.name.as_deref(),
+    );
+    let (id, pid) = match keep(settle(load).await, registry).await {
+        Ok((id, pid, _)) => (id, pid),
+        Err(e) => return frida_error(e),
+    };
+
+    let _ = ch
+        .send(Pipe {
+            msg: MsgType::Socket,
+            payload: pid.to_string(),
         })
-        .collect()
+        .await;
+
+    Response::json(
+        200,
+        &json!({
+            "id": id,
+            "pid": pid,
+            "package": spawn.program,
+            "message": format!("Spawned {} with Script Loaded Successfully", spawn.program),
+        }),
+    )
+}
+
+/// Returns the frida-server device's system parameters, such as the OS version and ABI.
+async fn device_parameters(registry: &Mutex<Registry>) -> Response {
+    let parameters = registry.lock().await.system_parameters();
+    match parameters {
+        Ok(parameters) => match parameters.await {
+            Ok(parameters) => Response::json(200, &json!(parameters)),
+            Err(e) => frida_error(e),
+        },
+        Err(e) => frida_error(e),
+    }
 }
 
-fn get_content_length(request: &str) -> Option<usize> {
-    request
-        .lines()
-        .find(|line| line.to_lowercase().starts_with("content-length:"))
-        .and_then(|line| line.split(':').nth(1))
-        .and_then(|len| len.trim().parse::<usize>().ok())
+/// Lists processes, optionally only `pids=1,2,3`, with the detail chosen by
+/// `scope=minimal|metadata|full`.
+async fn list_processes(request: &Request, registry: &Mutex<Registry>) -> Response {
+    let scope = match request.query.get("scope").map(String::as_str) {
+        None | Some("minimal") => Scope::Minimal,
+        Some("metadata") => Scope::Metadata,
+        Some("full") => Scope::Full,
+        Some(scope) => return bad_request(&format!("Invalid scope: {}", scope)),
+    };
+    let pids = match request.query.get("pids") {
+        Some(pids) => match pids
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<u32>, _>>()
+        {
+            Ok(pids) => pids,
+            Err(_) => return bad_request(&format!("Invalid pids: {}", pids)),
+        },
+        None => Vec::new(),
+    };
+    let options = ProcessQueryOptions::new().select_pids(pids).scope(scope);
+
+    let processes = registry.lock().await.processes(options);
+    match settle(processes).await {
+        Ok(processes) => {
+            let processes: Vec<_> = processes
+                .iter()
+                .map(|process| {
+                    json!({
+                        "pid": process.get_pid(),
+                        "name": process.get_name(),
+                        "parameters": process.get_parameters(),
+                    })
+                })
+                .collect();
+            Response::json(200, &json!(processes))
+        }
+        Err(e) => frida_error(e),
+    }
+}
+
+async fn list_scripts(registry: &Mutex<Registry>) -> Response {
+    let registry = registry.lock().await;
+    let scripts: Vec<_> = registry
+        .iter()
+        .map(|(id, entry)| {
+            json!({
+                "id": id,
+                "pid": entry.pid,
+                "package": entry.package,
+                "name": entry.name,
+                "realm": entry.realm,
+                "loaded_at": entry
+                    .loaded_at
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+                "by_package": entry.by_package,
+                "detached": entry.detached().map(|detached| json!({
+                    "reason": detached.reason,
+                    "crash": detached.crash,
+                    "at": detached
+                        .at
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or_default(),
+                })),
+            })
+        })
+        .collect();
+    Response::json(200, &json!(scripts))
+}
+
+async fn unload_script(id: ScriptId, registry: &Mutex<Registry>) -> Response {
+    let unload = registry.lock().await.unload(id);
+    match settle(unload).await {
+        Ok(()) => Response::json(200, &json!({ "id": id })),
+        Err(e) => frida_error(e),
+    }
+}
+
+async fn reload_script(id: ScriptId, request: &Request, registry: &Mutex<Registry>) -> Response {
+    let body = match script_source(request) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    let reload = registry.lock().await.reload(id, body);
+    match settle(reload).await {
+        Ok(injected) => {
+            let put = registry.lock().await.reloaded(injected);
+            if put.await {
+                Response::json(200, &json!({ "id": id }))
+            } else {
+                frida_error(Error::ScriptNotFound { id })
+            }
+        }
+        Err(e) => frida_error(e),
+    }
+}
+
+async fn script_messages(id: ScriptId, request: &Request, registry: &Mutex<Registry>) -> Response {
+    let since = match request.query.get("since").map(|since| since.parse::<u64>()) {
+        Some(Ok(since)) => since,
+        Some(Err(_)) => return bad_request("Invalid since parameter"),
+        None => 0,
+    };
+    let registry = registry.lock().await;
+    match registry.get(id) {
+        Some(entry) => {
+            let (messages, last) = entry.messages.since(since);
+            Response::json(200, &json!({ "messages": messages, "last": last }))
+        }
+        None => frida_error(Error::ScriptNotFound { id }),
+    }
+}
+
+async fn list_exports(id: ScriptId, registry: &Mutex<Registry>) -> Response {
+    let exports = registry.lock().await.list_exports(id);
+    match exports {
+        Ok(exports) => match exports.await {
+            Ok(exports) => Response::json(200, &json!(exports)),
+            Err(e) => frida_error(e),
+        },
+        Err(e) => frida_error(e),
+    }
+}
+
+async fn call_export(
+    id: ScriptId,
+    function: &str,
+    request: &Request,
+    registry: &Mutex<Registry>,
+) -> Response {
+    let args = if request.body.is_empty() {
+        Value::Array(Vec::new())
+    } else {
+        match serde_json::from_slice::<Value>(&request.body) {
+            Ok(args @ Value::Array(_)) => args,
+            _ => return bad_request("Arguments must be a JSON array"),
+        }
+    };
+    let timeout = match request.query.get("timeout").map(|ms| ms.parse::<u64>()) {
+        Some(Ok(ms)) => Duration::from_millis(ms),
+        Some(Err(_)) => return bad_request("Invalid timeout parameter"),
+        None => DEFAULT_RPC_TIMEOUT,
+    };
+
+    let call = registry.lock().await.call(id, function, args, timeout);
+    match call {
+        Ok(call) => match call.await {
+            Ok(value) => Response::json(200, &json!({ "result": value })),
+            Err(e) => frida_error(e),
+        },
+        Err(e) => frida_error(e),
+    }
+}
+
+/// Accepts either `{"message": ..., "data": "<base64>"}` as JSON, or a multipart form with a
+/// JSON `message` part and a binary `data` part.
+async fn post_message(id: ScriptId, request: &Request, registry: &Mutex<Registry>) -> Response {
+    let (message, data) = match request.multipart() {
+        Some(parts) => {
+            let message = parts
+                .iter()
+                .find(|part| part.name == "message")
+                .and_then(|part| serde_json::from_slice::<Value>(&part.data).ok());
+            let data = parts
+                .into_iter()
+                .find(|part| part.name == "data")
+                .map(|part| part.data);
+            match message {
+                Some(message) => (message, data),
+                None => return bad_request("Missing or invalid JSON message part"),
+            }
+        }
+        None => {
+            let mut body = match serde_json::from_slice::<Value>(&request.body) {
+                Ok(body @ Value::Object(_)) => body,
+                _ => return bad_request("Body must be a JSON object"),
+            };
+            let data = match body["data"].take() {
+                Value::Null => None,
+                Value::String(encoded) => match BASE64.decode(encoded) {
+                    Ok(data) => Some(data),
+                    Err(_) => return bad_request("data is not valid base64"),
+                },
+                _ => return bad_request("data must be a base64 string"),
+            };
+            match body.get_mut("message") {
+                Some(message) => (message.take(), data),
+                None => return bad_request("Missing message"),
+            }
+        }
+    };
+
+    match registry.lock().await.post(id, &message, data.as_deref()) {
+        Ok(()) => Response::json(200, &json!({ "id": id })),
+        Err(e) => frida_error(e),
+    }
+}
+
+fn script_source(request: &Request) -> Result<&str, Response> {
+    match std::str::from_utf8(&request.body) {
+        Ok(body) if !body.is_empty() => Ok(body),
+        Ok(_) => Err(bad_request("No Script Provided!")),
+        Err(_) => Err(bad_request("Script is not valid UTF-8")),
+    }
+}
+
+fn parse_id(id: &str) -> Result<ScriptId, Response> {
+    id.parse()
+        .map_err(|_| bad_request(&format!("Invalid script id: {}", id)))
+}
+
+/// Answers with `{error, code, kind, message}`, where `kind` is the [`ErrorKind`] name Lua
+/// scripts can branch on and `code` the raw Frida error code, if any.
+fn frida_error(e: Error) -> Response {
+    let kind = e.kind();
+    let status = match &e {
+        Error::RpcScriptDestroyed | Error::SessionDetached => 410,
+        // JavaScript exceptions, and syntax errors and the like in the submitted source.
+        Error::RpcJsError { .. } => 422,
+        Error::ScriptCreationError { .. } if kind == ErrorKind::InvalidArgument => 422,
+        _ => kind_status(kind),
+    };
+    let mut body = error_body(&e.to_string(), e.code(), kind, e.message());
+    match &e {
+        Error::RpcJsError { message } | Error::ScriptCreationError { message, .. }
+            if status == 422 =>
+        {
+            body["js_error"] = json!(message);
+        }
+        _ => {}
+    }
+    Response::json(status, &body)
+}
+
+fn kind_status(kind: ErrorKind) -> u16 {
+    match kind {
+        ErrorKind::ExecutableNotFound | ErrorKind::ProcessNotFound | ErrorKind::ScriptNotFound => {
+            404
+        }
+        ErrorKind::InvalidArgument => 400,
+        ErrorKind::PermissionDenied => 403,
+        ErrorKind::InvalidOperation | ErrorKind::AddressInUse => 409,
+        ErrorKind::ExecutableNotSupported => 422,
+        ErrorKind::NotSupported => 501,
+        ErrorKind::Protocol | ErrorKind::Transport => 502,
+        ErrorKind::ServerNotRunning => 503,
+        ErrorKind::ProcessNotResponding | ErrorKind::TimedOut => 504,
+        ErrorKind::Other => 500,
+    }
+}
+
+/// Rejects a malformed request.
+fn bad_request(message: &str) -> Response {
+    error_response(400, ErrorKind::InvalidArgument, message)
+}
+
+/// Rejects a request, in the same shape as Frida errors.
+fn error_response(status: u16, kind: ErrorKind, message: &str) -> Response {
+    let body = error_body(message, None, kind, None);
+    Response::json(status, &body)
+}
+
+/// `code` and `message` are those of the `GError` behind the error. Errors that do not come
+/// from Frida have a `null` code and repeat `error` as their message.
+fn error_body(error: &str, code: Option<i32>, kind: ErrorKind, message: Option<&str>) -> Value {
+    json!({
+        "error": error,
+        "code": code,
+        "kind": kind,
+        "message": message.unwrap_or(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_kinds_to_statuses() {
+        let statuses = [
+            (ErrorKind::ServerNotRunning, 503),
+            (ErrorKind::ExecutableNotFound, 404),
+            (ErrorKind::ExecutableNotSupported, 422),
+            (ErrorKind::ProcessNotFound, 404),
+            (ErrorKind::ScriptNotFound, 404),
+            (ErrorKind::ProcessNotResponding, 504),
+            (ErrorKind::InvalidArgument, 400),
+            (ErrorKind::InvalidOperation, 409),
+            (ErrorKind::PermissionDenied, 403),
+            (ErrorKind::AddressInUse, 409),
+            (ErrorKind::TimedOut, 504),
+            (ErrorKind::NotSupported, 501),
+            (ErrorKind::Protocol, 502),
+            (ErrorKind::Transport, 502),
+            (ErrorKind::Other, 500),
+        ];
+        for (kind, status) in statuses {
+            assert_eq!(kind_status(kind), status, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn reports_frida_errors_with_their_gerror() {
+        let body = error_body(
+            "Failed to attach: unable to find process",
+            Some(1),
+            ErrorKind::ProcessNotFound,
+            Some("unable to find process"),
+        );
+        assert_eq!(body["code"], 1);
+        assert_eq!(body["kind"], "process_not_found");
+        assert_eq!(body["message"], "unable to find process");
+    }
+
+    #[test]
+    fn repeats_the_error_as_message_without_a_gerror() {
+        let body = error_body("Missing message", None, ErrorKind::InvalidArgument, None);
+        assert_eq!(body["error"], "Missing message");
+        assert_eq!(body["code"], Value::Null);
+        assert_eq!(body["kind"], "invalid_argument");
+        assert_eq!(body["message"], "Missing message");
+    }
 }
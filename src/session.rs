@@ -6,41 +6,264 @@
 
 use crate::bind;
 
-use std::ffi::CString;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::future::Future;
 use std::marker::PhantomData;
 use std::ptr::null_mut;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use crate::error::take_error;
 use crate::script::{Script, ScriptOption};
-use crate::{Error, Result};
+use crate::variant::{hash_table_to_map, Variant};
+use crate::{connect_signal, disconnect_signals, Error, ObjectRef, Result, SendPtr, FRIDA};
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Which code a session instruments
+pub enum Realm {
+    /// The process's own code
+    #[default]
+    Native = bind::FridaRealm_FRIDA_REALM_NATIVE as _,
+
+    /// Code running under an emulator, e.g. ARM code translated on an x86 device
+    Emulated = bind::FridaRealm_FRIDA_REALM_EMULATED as _,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// Why a session was detached
+pub enum DetachReason {
+    /// The session was detached on our side
+    ApplicationRequested =
+        bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_APPLICATION_REQUESTED as _,
+
+    /// The process called exec and runs another program
+    ProcessReplaced =
+        bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_PROCESS_REPLACED as _,
+
+    /// The process exited or crashed
+    ProcessTerminated =
+        bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_PROCESS_TERMINATED as _,
+
+    /// The connection to frida-server dropped and the session did not persist
+    ConnectionTerminated =
+        bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_CONNECTION_TERMINATED as _,
+
+    /// The device went away
+    DeviceLost = bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_DEVICE_LOST as _,
+}
+
+impl From<bind::FridaSessionDetachReason> for DetachReason {
+    fn from(reason: bind::FridaSessionDetachReason) -> Self {
+        match reason {
+            bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_APPLICATION_REQUESTED => {
+                Self::ApplicationRequested
+            }
+            bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_PROCESS_REPLACED => {
+                Self::ProcessReplaced
+            }
+            bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_PROCESS_TERMINATED => {
+                Self::ProcessTerminated
+            }
+            bind::FridaSessionDetachReason_FRIDA_SESSION_DETACH_REASON_DEVICE_LOST => {
+                Self::DeviceLost
+            }
+            _ => Self::ConnectionTerminated,
+        }
+    }
+}
+
+/// Crash report of a process that died while a session was attached to it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrashReport {
+    /// Process ID of the crashed process.
+    pub pid: u32,
+    /// Name of the crashed process.
+    pub process_name: String,
+    /// One-line description of the crash.
+    pub summary: String,
+    /// Full report, e.g. the tombstone on Android.
+    pub report: String,
+    /// Extra details provided by the platform.
+    pub parameters: HashMap<String, Variant>,
+}
+
+impl CrashReport {
+    pub(crate) fn from_raw(crash_ptr: *mut bind::FridaCrash) -> Self {
+        let owned = |s: *const std::ffi::c_char| {
+            unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+        };
+        unsafe {
+            CrashReport {
+                pid: bind::frida_crash_get_pid(crash_ptr),
+                process_name: owned(bind::frida_crash_get_process_name(crash_ptr)),
+                summary: owned(bind::frida_crash_get_summary(crash_ptr)),
+                report: owned(bind::frida_crash_get_report(crash_ptr)),
+                parameters: hash_table_to_map(bind::frida_crash_get_parameters(crash_ptr)),
+            }
+        }
+    }
+}
+
+/// Represents a session signal handler.
+pub trait SessionHandler {
+    /// Handler called once when the session is detached, with the crash report if the process
+    /// crashed.
+    fn on_detached(&mut self, reason: DetachReason, crash: Option<&CrashReport>);
+}
+
+/// Session options
+pub struct SessionOptions<'a> {
+    pub(crate) options_ptr: *mut bind::FridaSessionOptions,
+    phantom: PhantomData<&'a bind::FridaSessionOptions>,
+}
+
+impl<'a> SessionOptions<'a> {
+    /// Create options for a native, non-persistent session
+    pub fn new() -> Self {
+        Self {
+            options_ptr: unsafe { bind::frida_session_options_new() },
+            phantom: PhantomData,
+        }
+    }
+
+    /// Keep the session and its scripts alive for up to `timeout` after the connection to
+    /// frida-server drops, so it can be [resumed](Session::resume)
+    pub fn persist_timeout(self, timeout: Duration) -> Self {
+        let secs = timeout.as_secs().try_into().unwrap_or(u32::MAX);
+        unsafe { bind::frida_session_options_set_persist_timeout(self.options_ptr, secs) }
+        self
+    }
+
+    /// Set the realm to instrument
+    pub fn realm(self, realm: Realm) -> Self {
+        unsafe { bind::frida_session_options_set_realm(self.options_ptr, realm as _) }
+        self
+    }
+
+    /// Set the agent loaded into the emulated realm, instead of the one frida-server ships
+    pub fn emulated_agent_path<S: AsRef<CStr>>(self, path: S) -> Self {
+        unsafe {
+            bind::frida_session_options_set_emulated_agent_path(
+                self.options_ptr,
+                path.as_ref().as_ptr(),
+            )
+        }
+        self
+    }
+}
+
+impl<'a> Default for SessionOptions<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Drop for SessionOptions<'a> {
+    fn drop(&mut self) {
+        unsafe { bind::frida_unref(self.options_ptr as _) }
+    }
+}
 
 /// Represents a Frida session.
 pub struct Session<'a> {
     session_ptr: *mut bind::_FridaSession,
+    detached_handlers: Arc<DetachedHandlers>,
+    signal_handler_id: bind::gulong,
     phantom: PhantomData<&'a bind::_FridaSession>,
 }
 
 impl<'a> Session<'a> {
     pub(crate) fn from_raw(session_ptr: *mut bind::_FridaSession) -> Session<'a> {
+        let handlers = Arc::new(DetachedHandlers::default());
+        let signal_handler_id = unsafe {
+            connect_signal(
+                session_ptr,
+                c"detached",
+                call_on_detached as *mut c_void,
+                Arc::as_ptr(&handlers) as *mut c_void,
+            )
+        };
+
         Session {
             session_ptr,
+            detached_handlers: handlers,
+            signal_handler_id,
             phantom: PhantomData,
         }
     }
 
+    /// Handles the `detached` signal for the session and wraps into [`SessionHandler`].
+    ///
+    /// The handler runs on the Frida main context. It is not called if the session is already
+    /// detached, check [`is_detached`](Session::is_detached) after adding it.
+    pub fn handle_detached<I: SessionHandler + Send + 'static>(
+        &mut self,
+        handler: I,
+    ) -> Result<()> {
+        self.detached_handlers
+            .0
+            .lock()
+            .unwrap()
+            .push(Box::new(handler));
+        Ok(())
+    }
+
     /// Returns if the session is detached or not.
     pub fn is_detached(&self) -> bool {
         unsafe { bind::frida_session_is_detached(self.session_ptr) == 1 }
     }
 
+    /// Returns how long the session outlives a dropped connection, zero if it does not.
+    pub fn persist_timeout(&self) -> Duration {
+        Duration::from_secs(
+            unsafe { bind::frida_session_get_persist_timeout(self.session_ptr) }.into(),
+        )
+    }
+
+    /// Reconnects a persistent session whose connection dropped, with its scripts intact.
+    pub fn resume(&self) -> Result<()> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        unsafe { bind::frida_session_resume_sync(self.session_ptr, null_mut(), &mut error) };
+
+        if error.is_null() {
+            Ok(())
+        } else {
+            let (code, message) = take_error(error);
+            Err(Error::SessionResumeFailed { code, message })
+        }
+    }
+
+    /// Resumes the session without blocking the calling thread.
+    pub fn resume_async(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let session = SendPtr::new(self.session_ptr);
+        FRIDA.run_async(
+            self.session_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_session_resume(session.get(), null_mut(), callback, user_data)
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe { bind::frida_session_resume_finish(session.get(), result, &mut error) };
+                if error.is_null() {
+                    Ok(())
+                } else {
+                    let (code, message) = take_error(error);
+                    Err(Error::SessionResumeFailed { code, message })
+                }
+            },
+        )
+    }
+
     /// Creates a [`Script`] attached to current session.
-    pub fn create_script<'b>(
-        &'a self,
-        source: &str,
-        option: &mut ScriptOption,
-    ) -> Result<Script<'b>>
-    where
-        'a: 'b,
-    {
+    ///
+    /// The script holds its own reference and may outlive this handle.
+    pub fn create_script(&self, source: &str, option: &mut ScriptOption) -> Result<Script<'a>> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
         match CString::new(source) {
             Ok(source) => {
@@ -56,13 +279,142 @@ impl<'a> Session<'a> {
                 if error.is_null() {
                     Ok(Script::from_raw(script))
                 } else {
-                    Err(Error::ScriptCreationError)
+                    let (code, message) = take_error(error);
+                    Err(Error::ScriptCreationError { code, message })
                 }
             }
             Err(_) => Err(Error::CStringFailed),
         }
     }
 
+    /// Creates a [`Script`] without blocking the calling thread.
+    ///
+    /// The returned future does not borrow the session or the options.
+    pub fn create_script_async(
+        &self,
+        source: &str,
+        option: &mut ScriptOption,
+    ) -> impl Future<Output = Result<Script<'static>>> + Send + 'static {
+        let session = SendPtr::new(self.session_ptr);
+        let create = CString::new(source).map(|source| {
+            let options = ObjectRef::new(option.as_mut_ptr());
+
+            FRIDA.run_async(
+                self.session_ptr as _,
+                move |callback, user_data| unsafe {
+                    bind::frida_session_create_script(
+                        session.get(),
+                        source.as_ptr(),
+                        options.get(),
+                        null_mut(),
+                        callback,
+                        user_data,
+                    );
+                },
+                move |result| {
+                    let mut error: *mut bind::GError = std::ptr::null_mut();
+                    let script = unsafe {
+                        bind::frida_session_create_script_finish(session.get(), result, &mut error)
+                    };
+                    if error.is_null() {
+                        Ok(Script::from_raw(script))
+                    } else {
+                        let (code, message) = take_error(error);
+                        Err(Error::ScriptCreationError { code, message })
+                    }
+                },
+            )
+        });
+
+        async move { create.map_err(|_| Error::CStringFailed)?.await }
+    }
+
+    /// Enables child gating: children the process forks, execs or spawns from now on are held
+    /// until resumed.
+    ///
+    /// Held children are listed by
+    /// [`Device::enumerate_pending_children`](crate::Device::enumerate_pending_children).
+    pub fn enable_child_gating(&self) -> Result<()> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        unsafe {
+            bind::frida_session_enable_child_gating_sync(self.session_ptr, null_mut(), &mut error)
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::ChildGatingFailed { code, message });
+        }
+
+        Ok(())
+    }
+
+    /// Enables child gating without blocking the calling thread.
+    pub fn enable_child_gating_async(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let session = SendPtr::new(self.session_ptr);
+        FRIDA.run_async(
+            self.session_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_session_enable_child_gating(
+                    session.get(),
+                    null_mut(),
+                    callback,
+                    user_data,
+                )
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe {
+                    bind::frida_session_enable_child_gating_finish(
+                        session.get(),
+                        result,
+                        &mut error,
+                    )
+                };
+                if !error.is_null() {
+                    let (code, message) = take_error(error);
+                    return Err(Error::ChildGatingFailed { code, message });
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Disables child gating. Children that are already held stay suspended.
+    pub fn disable_child_gating(&self) -> Result<()> {
+        let mut error: *mut bind::GError = std::ptr::null_mut();
+        unsafe {
+            bind::frida_session_disable_child_gating_sync(self.session_ptr, null_mut(), &mut error)
+        };
+
+        if !error.is_null() {
+            let (code, message) = take_error(error);
+            return Err(Error::ChildGatingFailed { code, message });
+        }
+
+        Ok(())
+    }
+
+    /// Detaches the session without blocking the calling thread.
+    pub fn detach_async(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let session = SendPtr::new(self.session_ptr);
+        FRIDA.run_async(
+            self.session_ptr as _,
+            move |callback, user_data| unsafe {
+                bind::frida_session_detach(session.get(), null_mut(), callback, user_data)
+            },
+            move |result| {
+                let mut error: *mut bind::GError = std::ptr::null_mut();
+                unsafe { bind::frida_session_detach_finish(session.get(), result, &mut error) };
+                if error.is_null() {
+                    Ok(())
+                } else {
+                    let (code, message) = take_error(error);
+                    Err(Error::SessionDetachError { code, message })
+                }
+            },
+        )
+    }
+
     /// Detaches the current session.
     pub fn detach(&self) -> Result<()> {
         let mut error: *mut bind::GError = std::ptr::null_mut();
@@ -73,13 +425,45 @@ impl<'a> Session<'a> {
         if error.is_null() {
             Ok(())
         } else {
-            Err(Error::SessionDetachError)
+            let (code, message) = take_error(error);
+            Err(Error::SessionDetachError { code, message })
         }
     }
 }
 
+/// Takes another reference to the session. The clone starts without the detached handlers of
+/// this one.
+impl<'a> Clone for Session<'a> {
+    fn clone(&self) -> Self {
+        unsafe { bind::_frida_g_object_ref(self.session_ptr as _) };
+        Session::from_raw(self.session_ptr)
+    }
+}
+
 impl<'a> Drop for Session<'a> {
     fn drop(&mut self) {
+        disconnect_signals(
+            self.session_ptr,
+            vec![self.signal_handler_id],
+            self.detached_handlers.clone(),
+        );
         unsafe { bind::frida_unref(self.session_ptr as _) }
     }
 }
+
+/// Handlers of a [`Session`], shared with the `detached` GSignal callback.
+#[derive(Default)]
+struct DetachedHandlers(Mutex<Vec<Box<dyn SessionHandler + Send>>>);
+
+unsafe extern "C" fn call_on_detached(
+    _session_ptr: *mut bind::_FridaSession,
+    reason: bind::FridaSessionDetachReason,
+    crash: *mut bind::FridaCrash,
+    user_data: *mut c_void,
+) {
+    let handlers = &*(user_data as *const DetachedHandlers);
+    let crash = (!crash.is_null()).then(|| CrashReport::from_raw(crash));
+    for handler in handlers.0.lock().unwrap().iter_mut() {
+        handler.on_detached(reason.into(), crash.as_ref());
+    }
+}
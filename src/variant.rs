@@ -1,7 +1,10 @@
 use crate::bind;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq)]
 /// GVariant types used by Frida
 pub enum Variant {
     /// String
@@ -10,21 +13,39 @@ pub enum Variant {
     /// Boolean value
     Boolean(bool),
 
-    /// Integer value
+    /// Integer value, from any signed type or unsigned type of up to 32 bits
     Int64(i64),
 
+    /// Unsigned 64-bit integer value
+    UInt64(u64),
+
+    /// Floating point value
+    Double(f64),
+
     /// Map
     Map(HashMap<String, Variant>),
 
     /// Array of Maps
     MapList(Vec<HashMap<String, Variant>>),
+
+    /// Array of Strings
+    StringList(Vec<String>),
+
+    /// Byte array
+    Bytes(Vec<u8>),
+
+    /// Any other array, or a tuple
+    Array(Vec<Variant>),
+
+    /// A type without a dedicated representation: its type string and its printed form
+    Unknown(String, String),
 }
 
 impl Variant {
     /// Construct a GVariant from a raw pointer
     pub(crate) unsafe fn from_ptr(variant: *mut bind::GVariant) -> Self {
         match variant_string(variant).as_str() {
-            "s" => {
+            "s" | "o" | "g" => {
                 let mut sz = 0;
                 let value = CStr::from_ptr(bind::_frida_g_variant_get_string(variant, &mut sz))
                     .to_string_lossy()
@@ -33,9 +54,141 @@ impl Variant {
             }
             "b" => Self::Boolean(bind::_frida_g_variant_get_boolean(variant) != bind::FALSE as i32),
             "x" => Self::Int64(bind::_frida_g_variant_get_int64(variant).into()),
+            "y" => Self::Int64(bind::_frida_g_variant_get_byte(variant).into()),
+            "n" => Self::Int64(bind::_frida_g_variant_get_int16(variant).into()),
+            "q" => Self::Int64(bind::_frida_g_variant_get_uint16(variant).into()),
+            "i" => Self::Int64(bind::_frida_g_variant_get_int32(variant).into()),
+            "u" => Self::Int64(bind::_frida_g_variant_get_uint32(variant).into()),
+            "t" => Self::UInt64(bind::_frida_g_variant_get_uint64(variant)),
+            "d" => Self::Double(bind::_frida_g_variant_get_double(variant)),
+            "v" => {
+                let inner = bind::_frida_g_variant_get_variant(variant);
+                let value = Self::from_ptr(inner);
+                bind::_frida_g_variant_unref(inner);
+                value
+            }
             "a{sv}" => Self::Map(sv_array_to_map(variant)),
             "aa{sv}" => Self::MapList(asv_array_to_maplist(variant)),
-            other => todo!("Unimplemented variant: {other}"),
+            "as" => {
+                let mut len = 0;
+                let strv = bind::_frida_g_variant_get_strv(variant, &mut len);
+                let list = (0..len)
+                    .map(|i| {
+                        CStr::from_ptr(*strv.add(i as usize))
+                            .to_string_lossy()
+                            .to_string()
+                    })
+                    .collect();
+                bind::_frida_g_free(strv as _);
+                Self::StringList(list)
+            }
+            "ay" => {
+                let mut len = 0;
+                let data = bind::_frida_g_variant_get_fixed_array(variant, &mut len, 1);
+                if data.is_null() {
+                    Self::Bytes(Vec::new())
+                } else {
+                    Self::Bytes(
+                        std::slice::from_raw_parts(data as *const u8, len as usize).to_vec(),
+                    )
+                }
+            }
+            other if other.starts_with("a{s") => Self::Map(
+                children(variant)
+                    .into_iter()
+                    .filter_map(|entry| match entry {
+                        Self::Array(mut kv) if kv.len() == 2 => {
+                            let value = kv.pop().unwrap();
+                            match kv.pop().unwrap() {
+                                Self::String(key) => Some((key, value)),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            // Dict entries come out as two-element arrays, folded into maps above.
+            other if other.starts_with(['a', '(', '{']) => Self::Array(children(variant)),
+            other => {
+                let printed = bind::_frida_g_variant_print(variant, bind::FALSE as _);
+                let value = CStr::from_ptr(printed).to_string_lossy().to_string();
+                bind::_frida_g_free(printed as _);
+                Self::Unknown(other.to_string(), value)
+            }
+        }
+    }
+
+    /// Build a new floating GVariant holding the same value
+    ///
+    /// Maps become `a{sv}`, other arrays `av`, and [`Unknown`](Variant::Unknown) values are
+    /// parsed back from their printed form.
+    pub(crate) fn to_gvariant(&self) -> *mut bind::GVariant {
+        unsafe {
+            match self {
+                Self::String(s) => {
+                    let s = CString::new(s.replace('\0', "")).unwrap();
+                    bind::_frida_g_variant_new_string(s.as_ptr())
+                }
+                Self::Boolean(b) => bind::_frida_g_variant_new_boolean(*b as _),
+                Self::Int64(num) => bind::_frida_g_variant_new_int64(*num),
+                Self::UInt64(num) => bind::_frida_g_variant_new_uint64(*num),
+                Self::Double(num) => bind::_frida_g_variant_new_double(*num),
+                Self::Map(m) => map_to_sv_array(m),
+                Self::MapList(l) => {
+                    new_array("a{sv}", l.iter().map(|m| map_to_sv_array(m)).collect())
+                }
+                Self::StringList(l) => new_array(
+                    "s",
+                    l.iter()
+                        .map(|s| Self::String(s.clone()).to_gvariant())
+                        .collect(),
+                ),
+                Self::Bytes(b) => {
+                    let element_type = bind::_frida_g_variant_type_new(c"y".as_ptr());
+                    let variant = bind::_frida_g_variant_new_fixed_array(
+                        element_type,
+                        b.as_ptr() as _,
+                        b.len() as _,
+                        1,
+                    );
+                    bind::_frida_g_variant_type_free(element_type);
+                    variant
+                }
+                Self::Array(a) => new_array(
+                    "v",
+                    a.iter()
+                        .map(|v| bind::_frida_g_variant_new_variant(v.to_gvariant()))
+                        .collect(),
+                ),
+                Self::Unknown(type_string, printed) => {
+                    let parsed = CString::new(type_string.as_str())
+                        .ok()
+                        .zip(CString::new(printed.as_str()).ok())
+                        .map_or(std::ptr::null_mut(), |(type_string, printed)| {
+                            let variant_type =
+                                bind::_frida_g_variant_type_new(type_string.as_ptr());
+                            let mut error: *mut bind::GError = std::ptr::null_mut();
+                            let parsed = bind::_frida_g_variant_parse(
+                                variant_type,
+                                printed.as_ptr(),
+                                std::ptr::null(),
+                                std::ptr::null_mut(),
+                                &mut error,
+                            );
+                            bind::_frida_g_variant_type_free(variant_type);
+                            if !error.is_null() {
+                                bind::_frida_g_error_free(error);
+                            }
+                            parsed
+                        });
+                    if parsed.is_null() {
+                        Self::String(printed.clone()).to_gvariant()
+                    } else {
+                        parsed
+                    }
+                }
+            }
         }
     }
 
@@ -53,6 +206,20 @@ impl Variant {
         Some(*i)
     }
 
+    /// Get the floating point value of a variant, if any
+    pub fn get_double(&self) -> Option<f64> {
+        let Self::Double(d) = self else { return None };
+        Some(*d)
+    }
+
+    /// Get the array value of a variant, if any
+    pub fn get_array(&self) -> Option<&[Variant]> {
+        let Self::Array(ref a) = self else {
+            return None;
+        };
+        Some(a)
+    }
+
     /// Get the boolean value of a variant, if any
     pub fn get_bool(&self) -> Option<bool> {
         let Self::Boolean(b) = self else { return None };
@@ -72,6 +239,188 @@ impl Variant {
         };
         Some(l)
     }
+
+    /// Get the string list value of a variant, if any
+    pub fn get_string_list(&self) -> Option<&[String]> {
+        let Self::StringList(ref l) = self else {
+            return None;
+        };
+        Some(l)
+    }
+
+    /// Get the byte array value of a variant, if any
+    pub fn get_bytes(&self) -> Option<&[u8]> {
+        let Self::Bytes(ref b) = self else {
+            return None;
+        };
+        Some(b)
+    }
+}
+
+impl From<String> for Variant {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for Variant {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<bool> for Variant {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<i32> for Variant {
+    fn from(value: i32) -> Self {
+        Self::Int64(value.into())
+    }
+}
+
+impl From<u32> for Variant {
+    fn from(value: u32) -> Self {
+        Self::Int64(value.into())
+    }
+}
+
+impl From<i64> for Variant {
+    fn from(value: i64) -> Self {
+        Self::Int64(value)
+    }
+}
+
+impl From<u64> for Variant {
+    fn from(value: u64) -> Self {
+        Self::UInt64(value)
+    }
+}
+
+impl From<f64> for Variant {
+    fn from(value: f64) -> Self {
+        Self::Double(value)
+    }
+}
+
+impl From<Vec<u8>> for Variant {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Bytes(value)
+    }
+}
+
+impl From<Vec<String>> for Variant {
+    fn from(value: Vec<String>) -> Self {
+        Self::StringList(value)
+    }
+}
+
+impl From<Vec<Variant>> for Variant {
+    fn from(value: Vec<Variant>) -> Self {
+        Self::Array(value)
+    }
+}
+
+impl From<HashMap<String, Variant>> for Variant {
+    fn from(value: HashMap<String, Variant>) -> Self {
+        Self::Map(value)
+    }
+}
+
+/// Serializes to plain JSON values, with byte arrays as base64 strings.
+impl Serialize for Variant {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::String(s) => s.serialize(serializer),
+            Self::Int64(num) => num.serialize(serializer),
+            Self::UInt64(num) => num.serialize(serializer),
+            Self::Double(num) => num.serialize(serializer),
+            Self::Boolean(b) => b.serialize(serializer),
+            Self::Map(m) => m.serialize(serializer),
+            Self::MapList(l) => l.serialize(serializer),
+            Self::StringList(l) => l.serialize(serializer),
+            Self::Bytes(b) => BASE64.encode(b).serialize(serializer),
+            Self::Array(a) => a.serialize(serializer),
+            Self::Unknown(_, printed) => printed.serialize(serializer),
+        }
+    }
+}
+
+/// Deserializes from plain JSON values. Arrays of only strings become
+/// [`StringList`](Variant::StringList), arrays of only objects [`MapList`](Variant::MapList);
+/// base64 strings stay strings.
+impl<'de> Deserialize<'de> for Variant {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(VariantVisitor)
+    }
+}
+
+struct VariantVisitor;
+
+impl<'de> Visitor<'de> for VariantVisitor {
+    type Value = Variant;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a string, boolean, number, array or map")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Variant, E> {
+        Ok(Variant::Boolean(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Variant, E> {
+        Ok(Variant::Int64(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Variant, E> {
+        Ok(i64::try_from(value).map_or(Variant::UInt64(value), Variant::Int64))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Variant, E> {
+        Ok(Variant::Double(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Variant, E> {
+        Ok(Variant::String(value.to_string()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Variant, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element::<Variant>()? {
+            items.push(item);
+        }
+        if !items.is_empty() && items.iter().all(|item| item.get_string().is_some()) {
+            let strings = items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Variant::String(s) => Some(s),
+                    _ => None,
+                })
+                .collect();
+            return Ok(Variant::StringList(strings));
+        }
+        if !items.is_empty() && items.iter().all(|item| item.get_map().is_some()) {
+            let maps = items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Variant::Map(m) => Some(m),
+                    _ => None,
+                })
+                .collect();
+            return Ok(Variant::MapList(maps));
+        }
+        Ok(Variant::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Variant, A::Error> {
+        let mut entries = HashMap::new();
+        while let Some((key, value)) = map.next_entry::<String, Variant>()? {
+            entries.insert(key, value);
+        }
+        Ok(Variant::Map(entries))
+    }
 }
 
 impl std::fmt::Debug for Variant {
@@ -79,13 +428,95 @@ impl std::fmt::Debug for Variant {
         match self {
             Self::String(s) => s.fmt(f),
             Self::Int64(num) => num.fmt(f),
+            Self::UInt64(num) => num.fmt(f),
+            Self::Double(num) => num.fmt(f),
             Self::Boolean(b) => b.fmt(f),
             Self::Map(m) => m.fmt(f),
             Self::MapList(l) => l.fmt(f),
+            Self::StringList(l) => l.fmt(f),
+            Self::Bytes(b) => write!(f, "<{} bytes>", b.len()),
+            Self::Array(a) => a.fmt(f),
+            Self::Unknown(type_string, printed) => write!(f, "{printed} ({type_string})"),
         }
     }
 }
 
+/// Builds a `GHashTable` of string keys and `GVariant` values owning copies of `map`.
+pub(crate) unsafe fn hash_table_from_map(map: &HashMap<String, Variant>) -> *mut bind::GHashTable {
+    let ht = bind::_frida_g_hash_table_new_full(
+        Some(bind::_frida_g_str_hash),
+        Some(bind::_frida_g_str_equal),
+        Some(bind::_frida_g_free),
+        Some(unref_variant),
+    );
+    for (key, value) in map {
+        let key = CString::new(key.replace('\0', "")).unwrap();
+        bind::_frida_g_hash_table_insert(
+            ht,
+            bind::_frida_g_strdup(key.as_ptr()) as _,
+            bind::_frida_g_variant_ref_sink(value.to_gvariant()) as _,
+        );
+    }
+    ht
+}
+
+unsafe extern "C" fn unref_variant(variant: bind::gpointer) {
+    bind::_frida_g_variant_unref(variant as _);
+}
+
+/// Builds an array of `element_type` from floating `children`.
+unsafe fn new_array(element_type: &str, children: Vec<*mut bind::GVariant>) -> *mut bind::GVariant {
+    let element_type = CString::new(element_type).unwrap();
+    let element_type = bind::_frida_g_variant_type_new(element_type.as_ptr());
+    let array =
+        bind::_frida_g_variant_new_array(element_type, children.as_ptr(), children.len() as _);
+    bind::_frida_g_variant_type_free(element_type);
+    array
+}
+
+unsafe fn map_to_sv_array(map: &HashMap<String, Variant>) -> *mut bind::GVariant {
+    let entries = map
+        .iter()
+        .map(|(key, value)| {
+            bind::_frida_g_variant_new_dict_entry(
+                Variant::String(key.clone()).to_gvariant(),
+                bind::_frida_g_variant_new_variant(value.to_gvariant()),
+            )
+        })
+        .collect();
+    new_array("{sv}", entries)
+}
+
+/// Copies a `GHashTable` of string keys and `GVariant` values.
+pub(crate) unsafe fn hash_table_to_map(ht: *mut bind::GHashTable) -> HashMap<String, Variant> {
+    let mut iter: bind::GHashTableIter = std::mem::MaybeUninit::zeroed().assume_init();
+    bind::_frida_g_hash_table_iter_init(&mut iter, ht);
+    let size = bind::_frida_g_hash_table_size(ht);
+    let mut map = HashMap::with_capacity(size as usize);
+
+    let mut key = std::ptr::null_mut();
+    let mut val = std::ptr::null_mut();
+    while bind::_frida_g_hash_table_iter_next(&mut iter, &mut key, &mut val) != bind::FALSE as i32 {
+        let key = CStr::from_ptr(key as _);
+        let val = Variant::from_ptr(val as _);
+        map.insert(key.to_string_lossy().to_string(), val);
+    }
+    map
+}
+
+/// Converts every child of a container: array elements, tuple members or dict entry halves.
+unsafe fn children(variant: *mut bind::GVariant) -> Vec<Variant> {
+    let len = bind::_frida_g_variant_n_children(variant);
+    (0..len)
+        .map(|i| {
+            let child = bind::_frida_g_variant_get_child_value(variant, i);
+            let value = Variant::from_ptr(child);
+            bind::_frida_g_variant_unref(child);
+            value
+        })
+        .collect()
+}
+
 unsafe fn variant_string(variant: *mut bind::GVariant) -> String {
     CStr::from_ptr(bind::_frida_g_variant_get_type_string(variant))
         .to_string_lossy()
@@ -131,3 +562,89 @@ unsafe fn asv_array_to_maplist(variant: *mut bind::GVariant) -> Vec<HashMap<Stri
 
     ret
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip(variant: &Variant) -> Variant {
+        serde_json::from_value(serde_json::to_value(variant).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_scalars() {
+        for variant in [
+            Variant::String("com.example.game".into()),
+            Variant::Boolean(true),
+            Variant::Int64(-42),
+            Variant::UInt64(u64::MAX),
+            Variant::Double(1.5),
+        ] {
+            assert_eq!(round_trip(&variant), variant);
+        }
+    }
+
+    #[test]
+    fn round_trips_containers() {
+        let map = HashMap::from([
+            ("arch".to_string(), Variant::from("arm64")),
+            ("api".to_string(), Variant::from(33)),
+        ]);
+        for variant in [
+            Variant::Map(map.clone()),
+            Variant::MapList(vec![map.clone(), HashMap::new()]),
+            Variant::StringList(vec!["a".into(), "b".into()]),
+            Variant::Array(vec![
+                Variant::from(1),
+                Variant::from("a"),
+                Variant::from(true),
+            ]),
+            Variant::Array(Vec::new()),
+        ] {
+            assert_eq!(round_trip(&variant), variant);
+        }
+    }
+
+    #[test]
+    fn serializes_bytes_as_base64_and_unknown_as_printed() {
+        assert_eq!(
+            serde_json::to_value(Variant::Bytes(vec![0, 1, 2, 255])).unwrap(),
+            json!("AAEC/w==")
+        );
+        assert_eq!(
+            serde_json::to_value(Variant::Unknown("mi".into(), "just 3".into())).unwrap(),
+            json!("just 3")
+        );
+    }
+
+    #[test]
+    fn deserializes_plain_json() {
+        let variant: Variant = serde_json::from_value(json!({
+            "os": { "id": "android", "version": "14" },
+            "tags": ["a", "b"],
+            "mixed": [1, "a"],
+            "apps": [{ "pid": 1 }],
+            "big": 18446744073709551615u64,
+        }))
+        .unwrap();
+        let map = variant.get_map().unwrap();
+        assert_eq!(
+            map["os"].get_map().unwrap()["version"].get_string(),
+            Some("14")
+        );
+        assert_eq!(
+            map["tags"].get_string_list(),
+            Some(&["a".to_string(), "b".to_string()][..])
+        );
+        assert_eq!(
+            map["mixed"],
+            Variant::Array(vec![Variant::Int64(1), Variant::from("a")])
+        );
+        assert_eq!(
+            map["apps"].get_maplist().unwrap()[0]["pid"].get_int(),
+            Some(1)
+        );
+        assert_eq!(map["big"], Variant::UInt64(u64::MAX));
+    }
+}